@@ -0,0 +1,155 @@
+//! `picnamion check-config [DIR]`: checks the configuration that applies to files in DIR, so that
+//! mistakes show up now rather than as a panic halfway through a run.  Every filename regex must
+//! compile and have the named groups handle_image needs, and every example filename given with a
+//! regex must produce the timestamp it says it does.  The built-in patterns' examples are checked
+//! too, whether or not the configuration uses them.
+
+use crate::configuration::Configuration;
+use crate::sources::{self, CandidateTime, FileRegex};
+use crate::{QUIET, metadata, patterns};
+use jiff::Timestamp;
+use jiff::civil::{Date, DateTime, Time};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+/// Prints what's wrong, if anything; returns the number of problems found
+pub fn run(configuration: &Configuration, dir: &Path) -> usize {
+    // The filename source talks about what it finds
+    QUIET.store(true, Ordering::Relaxed);
+
+    // This is where regexes that don't compile get caught
+    let settings = match configuration.settings_for(dir) {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("ERROR: Failed to read configuration: {e}");
+            return 1;
+        }
+    };
+
+    let mut problems = 0;
+    let mut problem = |message: String| {
+        println!("ERROR: {message}");
+        problems += 1;
+    };
+
+    if let Err(e) = sources::from_settings(&settings) {
+        problem(e);
+    }
+    if let Err(e) = metadata::from_settings(&settings) {
+        problem(e);
+    }
+    if let Err(e) = settings.home_tz() {
+        problem(format!("{e:?}"));
+    }
+    if let Err(e) = settings.forced_tz() {
+        problem(format!("{e:?}"));
+    }
+    if let Err(e) = settings.check_tolerances() {
+        problem(e);
+    }
+
+    let mut regexes: Vec<FileRegex> = settings
+        .file_regexes
+        .iter()
+        .chain(&settings.extra_file_regexes)
+        .cloned()
+        .collect();
+    let configured = regexes.len();
+    // The built-in patterns that aren't in use get checked too, so that they're known to work
+    // before anybody uses them
+    for builtin in patterns::BUILTINS {
+        if !regexes.iter().any(|r| r.builtin == Some(builtin.name)) {
+            regexes.push(FileRegex::from_builtin(builtin));
+        }
+    }
+    let mut examples = 0;
+    for file_regex in &regexes {
+        let regex = &file_regex.regex;
+        if let Err(e) = sources::check_groups(regex) {
+            problem(format!("Regex {regex} {e}"));
+            // The examples would just panic
+            continue;
+        }
+
+        for example in &file_regex.examples {
+            examples += 1;
+            let expected = match Expected::parse(&example.timestamp) {
+                Ok(expected) => expected,
+                Err(e) => {
+                    problem(format!(
+                        "Example {:?} for regex {} has a bad timestamp {:?}: {}",
+                        example.filename, regex, example.timestamp, e
+                    ));
+                    continue;
+                }
+            };
+            match sources::filename_timestamp(regex, &example.filename, &settings) {
+                Ok(Some(found)) if expected.matches(&found) => {}
+                Ok(Some(found)) => problem(format!(
+                    "Regex {} got {} from example {:?}, not {}",
+                    regex,
+                    found
+                        .instant()
+                        .filter(|_| matches!(expected, Expected::Instant(_)))
+                        .map(|ts| ts.to_string())
+                        .or_else(|| found.wall_clock().map(|datetime| datetime.to_string()))
+                        .unwrap_or_else(|| found.to_string()),
+                    example.filename,
+                    example.timestamp
+                )),
+                Ok(None) => problem(format!(
+                    "Regex {} doesn't match its example {:?}",
+                    regex, example.filename
+                )),
+                Err(e) => problem(format!(
+                    "Regex {} failed on its example {:?}: {:?}",
+                    regex, example.filename, e
+                )),
+            }
+        }
+    }
+
+    if problems == 0 {
+        println!(
+            "INFO: Configuration is OK; {} filename regexes, {} built-in patterns (version {}), {} examples checked.",
+            configured,
+            patterns::BUILTINS.len(),
+            patterns::VERSION,
+            examples
+        );
+    }
+    problems
+}
+
+/// What an example says its filename should give
+enum Expected {
+    /// A timestamp with an offset, like "2025-08-11T18:58:49.536Z", which doesn't depend on the
+    /// home time zone
+    Instant(Timestamp),
+    /// A wall clock time; date-only examples can just give the date
+    WallClock(DateTime),
+}
+
+impl Expected {
+    fn parse(timestamp: &str) -> Result<Expected, jiff::Error> {
+        if let Ok(instant) = timestamp.parse::<Timestamp>() {
+            return Ok(Expected::Instant(instant));
+        }
+        timestamp
+            .parse::<DateTime>()
+            .or_else(|e| {
+                timestamp
+                    .parse::<Date>()
+                    .map(|date| date.to_datetime(Time::midnight()))
+                    .map_err(|_| e)
+            })
+            .map(Expected::WallClock)
+    }
+
+    fn matches(&self, found: &CandidateTime) -> bool {
+        match self {
+            Expected::Instant(instant) => found.instant() == Some(*instant),
+            Expected::WallClock(datetime) => found.wall_clock() == Some(*datetime),
+        }
+    }
+}
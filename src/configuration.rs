@@ -0,0 +1,266 @@
+//! Where settings come from.  Each layer can override any setting from the layers before it:
+//!
+//!   1. the built-in defaults (settings/prod.json5, compiled in)
+//!   2. /etc/picnamion/config.json5
+//!   3. $XDG_CONFIG_HOME/picnamion/config.json5 (usually ~/.config/picnamion/config.json5)
+//!   4. settings/{prod,test}.json5 above the executable or in the current directory, or whatever
+//!      PICNAMION_CONFIG_FILE names
+//!   5. .picnamion.json5 files in the directory of the file being looked at and its parents,
+//!      outermost first, so that any directory can change settings for everything under it
+//!   6. PICNAMION_* environment variables
+//!   7. `--set KEY=VALUE` on the command line
+//!
+//! All the files are optional, except one named by PICNAMION_CONFIG_FILE.
+
+use crate::{Settings, get_environment, overrides};
+use config::{ConfigError, FileFormat, Source};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The settings picnamion ships with, so it works wherever it's installed; a settings file only
+/// needs to contain what it wants to change
+const DEFAULT_SETTINGS: &str = include_str!("../settings/prod.json5");
+
+const SYSTEM_CONFIG: &str = "/etc/picnamion/config.json5";
+
+/// The name of the per-directory settings file
+pub const DIRECTORY_CONFIG: &str = ".picnamion.json5";
+
+/// Describes where a setting came from, for `config show --origin`
+const DEFAULT_ORIGIN: &str = "built-in defaults";
+
+struct Layer {
+    /// Where this layer's settings come from, for people
+    origin: String,
+    source: Box<dyn Source + Send + Sync>,
+}
+
+impl Layer {
+    fn file(path: PathBuf, required: bool) -> Self {
+        Layer {
+            origin: path.display().to_string(),
+            source: Box::new(config::File::from(path).required(required)),
+        }
+    }
+}
+
+pub struct Configuration {
+    /// The layers below the per-directory ones, lowest priority first
+    base: Vec<Layer>,
+    /// The layers above the per-directory ones, lowest priority first
+    top: Vec<Layer>,
+    /// Whether .picnamion.json5 and overrides files in a file's directory and its parents count
+    directories: bool,
+    /// Settings already worked out, by directory
+    cache: RefCell<HashMap<PathBuf, Rc<Settings>>>,
+}
+
+impl Configuration {
+    /// Finds all the configuration layers; `overrides` are the KEY=VALUE pairs from --set
+    pub fn discover(overrides: &[(String, String)]) -> Self {
+        let mut layers = vec![defaults()];
+
+        layers.push(Layer::file(SYSTEM_CONFIG.into(), false));
+
+        if let Some(dir) = xdg_config_home() {
+            layers.push(Layer::file(dir.join("picnamion/config.json5"), false));
+        }
+
+        // A file that was asked for by name had better exist; the usual one is optional
+        match env::var("PICNAMION_CONFIG_FILE") {
+            Ok(name) => layers.push(Layer::file(name.into(), true)),
+            Err(_) => layers.push(Layer::file(
+                settings_directory().join(format!("{}.json5", get_environment().as_str())),
+                false,
+            )),
+        }
+
+        let base = std::mem::take(&mut layers);
+
+        // Add in settings from environment variables (with a prefix of PICNAMION and '__' as
+        // separator); e.g. `PICNAMION_EXIFTOOL_PATH=/opt/bin/exiftool` would set
+        // `Settings.exiftool_path`
+        layers.push(Layer {
+            origin: "environment".to_string(),
+            source: Box::new(
+                config::Environment::with_prefix("PICNAMION")
+                    .prefix_separator("_")
+                    .separator("__"),
+            ),
+        });
+
+        layers.extend(overrides.iter().map(set));
+
+        Configuration {
+            base,
+            top: layers,
+            directories: true,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Just the built-in defaults and `overrides`, with no settings or overrides files and no
+    /// environment variables, so that the result is the same on any machine
+    pub fn builtin(overrides: &[(String, String)]) -> Self {
+        Configuration {
+            base: vec![defaults()],
+            top: overrides.iter().map(set).collect(),
+            directories: false,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// All the layers that apply to files in `dir`, lowest priority first
+    fn layers_for(&self, dir: &Path) -> Vec<Layer> {
+        let directory_layers = directory_configs(dir)
+            .into_iter()
+            .filter(|_| self.directories)
+            .map(|path| Layer::file(path, true));
+        self.base
+            .iter()
+            .chain(directory_layers.collect::<Vec<_>>().iter())
+            .chain(&self.top)
+            .map(|layer| Layer {
+                origin: layer.origin.clone(),
+                source: layer.source.clone_into_box(),
+            })
+            .collect()
+    }
+
+    /// The settings for files in `dir`
+    pub fn settings_for(&self, dir: &Path) -> Result<Rc<Settings>, ConfigError> {
+        let dir = std::path::absolute(dir).map_err(|e| ConfigError::Foreign(Box::new(e)))?;
+        if let Some(settings) = self.cache.borrow().get(&dir) {
+            return Ok(settings.clone());
+        }
+
+        let sources: Vec<Box<dyn Source + Send + Sync>> = self
+            .layers_for(&dir)
+            .into_iter()
+            .map(|layer| layer.source)
+            .collect();
+        let mut settings = config::Config::builder()
+            .add_source(sources)
+            .build()?
+            .try_deserialize::<Settings>()?;
+        if self.directories {
+            settings.overrides =
+                overrides::for_directory(&dir, &settings).map_err(ConfigError::Message)?;
+        }
+        let settings = Rc::new(settings);
+        self.cache.borrow_mut().insert(dir, settings.clone());
+        Ok(settings)
+    }
+
+    /// The settings for a file, which depend on the directory it's in
+    pub fn settings_for_file(&self, file: &Path) -> Result<Rc<Settings>, ConfigError> {
+        match file.parent() {
+            Some(dir) if dir != Path::new("") => self.settings_for(dir),
+            _ => self.settings_for(Path::new(".")),
+        }
+    }
+
+    /// Every setting for files in `dir` with its effective value and the layer it came from
+    pub fn show(
+        &self,
+        dir: &Path,
+    ) -> Result<Vec<(String, serde_json::Value, String)>, ConfigError> {
+        let settings = serde_json::to_value(&*self.settings_for(dir)?)
+            .map_err(|e| ConfigError::Message(e.to_string()))?;
+
+        // The last layer to mention a setting is the one that decided it
+        let mut origins: BTreeMap<String, String> = BTreeMap::new();
+        for layer in self.layers_for(&std::path::absolute(dir).unwrap_or(dir.to_path_buf())) {
+            for key in layer.source.collect()?.keys() {
+                let top = key.split('.').next().unwrap_or(key).to_string();
+                let origin = if layer.origin == "environment" {
+                    format!(
+                        "environment (PICNAMION_{})",
+                        key.to_uppercase().replace('.', "__")
+                    )
+                } else {
+                    layer.origin.clone()
+                };
+                origins.insert(top, origin);
+            }
+        }
+
+        Ok(settings
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| {
+                let origin = origins
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| DEFAULT_ORIGIN.to_string());
+                (key.clone(), value.clone(), origin)
+            })
+            .collect())
+    }
+}
+
+/// The layer with the settings picnamion ships with
+fn defaults() -> Layer {
+    Layer {
+        origin: DEFAULT_ORIGIN.to_string(),
+        source: Box::new(config::File::from_str(DEFAULT_SETTINGS, FileFormat::Json5)),
+    }
+}
+
+/// The layer for one `--set KEY=VALUE`
+fn set((key, value): &(String, String)) -> Layer {
+    // Values are JSON if they can be, so that lists and numbers work; otherwise they're just
+    // strings
+    let value = serde_json::from_str::<serde_json::Value>(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.clone()));
+    let json = serde_json::json!({ key: value }).to_string();
+    Layer {
+        origin: format!("command line (--set {key})"),
+        source: Box::new(config::File::from_str(&json, FileFormat::Json)),
+    }
+}
+
+fn xdg_config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(dir.into()),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+/// The settings directory: the first one found walking up from the executable, or else the one
+/// in the current directory
+fn settings_directory() -> PathBuf {
+    let exe_path: PathBuf = match env::current_exe() {
+        Ok(x) => x,
+        Err(e) => panic!("failed to get current exe path: {e}"),
+    };
+
+    // Search up for a settings directory
+    let mut cur_dir = exe_path.parent();
+    while let Some(dir) = cur_dir
+        && dir != Path::new("")
+    {
+        if dir.join("settings").exists() {
+            return dir.join("settings");
+        }
+        cur_dir = dir.parent();
+    }
+
+    let base_path = env::current_dir().expect("Failed to determine the current directory");
+    base_path.join("settings")
+}
+
+/// The per-directory settings files that apply to `dir`, outermost first
+fn directory_configs(dir: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = dir
+        .ancestors()
+        .map(|ancestor| ancestor.join(DIRECTORY_CONFIG))
+        .filter(|path| path.is_file())
+        .collect();
+    found.reverse();
+    found
+}
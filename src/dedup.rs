@@ -0,0 +1,99 @@
+//! Finding files in a run that are byte-for-byte copies of each other (the same photo saved from
+//! Telegram and from the phone's gallery, say), so that they all end up with the same prefix.
+
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::trace::Step;
+use crate::{Decision, Outcome};
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Groups identical files, reports the groups, and gives every file in a group the most confident
+/// decision any of them got.  Returns the indexes of the spare copies, i.e. every file in a group
+/// except the one whose decision was used.
+pub fn share_decisions(decisions: &mut [Decision]) -> HashSet<usize> {
+    let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, decision) in decisions.iter().enumerate() {
+        if let Some(hash) = &decision.hash {
+            by_hash.entry(hash.clone()).or_default().push(index);
+        }
+    }
+
+    // Sorted so that the report comes out in the order the files were processed
+    let mut groups: Vec<Vec<usize>> = by_hash.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+
+    let mut spares = HashSet::new();
+    if groups.is_empty() {
+        return spares;
+    }
+
+    say!("\n\n********************** duplicates\n");
+
+    for group in groups {
+        // The most confident decision in the group wins; ties go to whichever file came first
+        let best = group
+            .iter()
+            .filter_map(|&index| match decisions[index].outcome {
+                Outcome::Decided { basis, .. } => Some((basis, index)),
+                _ => None,
+            })
+            .max_by_key(|(basis, index)| (basis.confidence(), Reverse(*index)));
+        let keeper = best.map_or(group[0], |(_, index)| index);
+
+        say!(
+            "INFO: These files are exact duplicates of each other (sha256 {}):",
+            decisions[keeper].hash.as_deref().unwrap_or_default()
+        );
+        for &index in &group {
+            say!(
+                "  {}{}",
+                decisions[index].filename.display(),
+                if index == keeper { " (kept)" } else { "" }
+            );
+        }
+
+        if best.is_none() {
+            say!("WARNING: None of them could be decided, so there's no decision to share.");
+            continue;
+        }
+
+        let outcome = decisions[keeper].outcome.clone();
+        for &index in &group {
+            if index == keeper {
+                continue;
+            }
+            if let Outcome::Decided { ts, .. } = outcome
+                && !matches!(decisions[index].outcome, Outcome::Decided { ts: old_ts, .. } if old_ts == ts)
+            {
+                say!(
+                    "INFO: Using the decision for {} for its duplicate {} as well: prefix {}",
+                    decisions[keeper].filename.display(),
+                    decisions[index].filename.display(),
+                    crate::prefix_for(ts)
+                );
+            }
+            let step = Step::new(
+                "exact_duplicate",
+                format!(
+                    "Identical contents to {}, so using its decision",
+                    decisions[keeper].filename.display()
+                ),
+            );
+            decisions[index].trace.push(step);
+            decisions[index].outcome = outcome.clone();
+            spares.insert(index);
+        }
+    }
+
+    spares
+}
@@ -0,0 +1,2097 @@
+//! picnamion works out when each picture or video was taken and renames it with that time as a
+//! prefix.  The command line is in main.rs; `decide` is the way in for anything else, like the
+//! golden tests.
+
+use jiff::civil::{Date, DateTime, Time};
+use jiff::fmt::friendly::{Direction, SpanPrinter};
+use jiff::{Zoned, tz, tz::TimeZone};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, fs};
+
+use error_stack::{ResultExt, report};
+use regex::Regex;
+
+/// Set by commands (like explain) that print their own report instead of the usual running
+/// commentary
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// println!, unless we've been asked to be quiet
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod check_config;
+pub mod configuration;
+mod dedup;
+pub mod metadata;
+mod native;
+mod organize;
+mod overrides;
+mod patterns;
+mod phash;
+mod review;
+pub mod sources;
+mod summary;
+mod trace;
+mod watch;
+use configuration::Configuration;
+use metadata::MetadataBackend;
+use organize::Organize;
+use sources::{Candidate, CandidateTime, FileRegex, SourceInput, TimestampSource};
+use summary::{FailOn, FileResult, Summary};
+use trace::Step;
+
+/// One piece of evidence for a timestamp, like "EXIF DateTimeOriginal", and what it's worth
+#[derive(Clone, Debug, PartialEq)]
+struct Tag {
+    name: String,
+    score: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct PicTimeStamp {
+    ts: Zoned,
+    tags: Vec<Tag>,
+    score: u32,
+}
+
+impl PicTimeStamp {
+    /// One line summary, for decision traces
+    pub fn describe(&self) -> String {
+        format!(
+            "{} (score {}: {})",
+            self.ts,
+            self.score,
+            self.tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    pub fn rescore(&mut self) {
+        self.score = self.tags.iter().map(|tag| tag.score).sum();
+    }
+
+    /// Is this just the fallback from the file system timestamps?
+    pub fn is_file_earliest(&self) -> bool {
+        self.tags.len() == 1 && self.tags[0].name == "File Earliest"
+    }
+}
+
+/// A timestamp found in the filename, with every regex that found it.  Two regexes that get the
+/// same time from a name are the same evidence, not two competing answers.
+#[derive(Clone, Debug, PartialEq)]
+struct FilenameMatch {
+    datetime: DateTime,
+    /// When the name says which time zone it's in (an offset or tz group), the moment it means;
+    /// that's matched against the metadata instead of the wall clock
+    zoned: Option<Zoned>,
+    /// Highest priority (earliest in the configuration) first
+    regexes: Vec<String>,
+}
+
+impl FilenameMatch {
+    /// Gathers filename timestamps, in priority order, into one FilenameMatch per distinct time;
+    /// times that only differ in fractions of a second count as the same, and keep the fractions
+    /// from the highest priority regex that had them
+    fn gather(filename_timestamps: Vec<(DateTime, Option<Zoned>, String)>) -> Vec<FilenameMatch> {
+        let mut matches: Vec<FilenameMatch> = vec![];
+        for (datetime, zoned, regex) in filename_timestamps {
+            match matches
+                .iter_mut()
+                .find(|m| to_the_second(m.datetime) == to_the_second(datetime))
+            {
+                Some(existing) => {
+                    if existing.datetime.subsec_nanosecond() == 0 {
+                        existing.datetime = datetime;
+                    }
+                    if existing.zoned.is_none() {
+                        existing.zoned = zoned;
+                    }
+                    existing.regexes.push(regex);
+                }
+                None => matches.push(FilenameMatch {
+                    datetime,
+                    zoned,
+                    regexes: vec![regex],
+                }),
+            }
+        }
+        matches
+    }
+}
+
+impl std::fmt::Display for FilenameMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.zoned {
+            Some(zoned) => write!(f, "{zoned}"),
+            None => write!(f, "{}", self.datetime),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MyError {
+    #[error("exiftool couldn't read {path}")]
+    ExifTool { path: PathBuf },
+    #[error("could not read {path}")]
+    ReadFile { path: PathBuf },
+    #[error("could not parse {group} {tag} {value:?} in {path}")]
+    BadTag {
+        path: PathBuf,
+        group: String,
+        tag: String,
+        value: String,
+    },
+    #[error("the EXIF offset tags in {path} disagree: {}", .tags.join(", "))]
+    ConflictingOffsets { path: PathBuf, tags: Vec<String> },
+    #[error("regex {regex} got a bad {part} {value:?} from {filename}")]
+    BadFilenameTimestamp {
+        regex: String,
+        filename: String,
+        part: &'static str,
+        value: String,
+    },
+    #[error("bad time zone {0:?}")]
+    TimeZone(String),
+    #[error("could not {0}")]
+    TimeArithmetic(String),
+    #[error("could not move {from} to {to}")]
+    Move { from: PathBuf, to: PathBuf },
+    #[error("could not run {0}")]
+    Command(String),
+    /// The file has already been renamed by the time this can happen
+    #[error("renamed to {path}, but could not rotate it with video_hard_rotate.sh")]
+    Rotate { path: PathBuf },
+    #[error("bad configuration")]
+    Configuration,
+    #[error("could not read or write the review sidecar {}", .path.display())]
+    Sidecar { path: PathBuf },
+    #[error("could not watch {}", .path.display())]
+    Watch { path: PathBuf },
+    #[error("panicked")]
+    Panic,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Settings {
+    pub file_regexes: Vec<FileRegex>,
+    /// How many bits (out of 64) two images' perceptual hashes can differ by and still be
+    /// considered the same picture
+    #[serde(default = "default_near_duplicate_distance")]
+    pub near_duplicate_distance: u32,
+    /// Where candidate timestamps come from, in order; see sources.rs for the names
+    #[serde(default = "default_sources")]
+    pub sources: Vec<String>,
+    /// What reads file metadata: "exiftool", "native" (no exiftool needed, common formats only)
+    /// or "auto" (native, falling back to exiftool for anything else)
+    #[serde(default = "default_metadata_backend")]
+    pub metadata_backend: String,
+    /// The exiftool to run; just "exiftool" means whichever one is on the PATH
+    #[serde(default = "default_exiftool_path")]
+    pub exiftool_path: PathBuf,
+    /// Timestamps with no real time zone are assumed to be in this one
+    #[serde(default = "default_home_time_zone")]
+    pub home_time_zone: String,
+    /// More filename regexes, tried after file_regexes; handy in a .picnamion.json5, since setting
+    /// file_regexes there replaces the whole list
+    #[serde(default)]
+    pub extra_file_regexes: Vec<FileRegex>,
+    /// Added to metadata and filename timestamps, for a camera whose clock was set wrong; like
+    /// "-3m 20s" or "PT16H"
+    #[serde(default)]
+    pub clock_offset: Option<jiff::SignedDuration>,
+    /// Show every timestamp in this time zone, treating filename timestamps (and metadata ones
+    /// with no real time zone) as being in home_time_zone first; for a trip where the camera stayed
+    /// on home time
+    #[serde(default)]
+    pub force_time_zone: Option<String>,
+    /// Sources to leave out of `sources`
+    #[serde(default)]
+    pub disabled_sources: Vec<String>,
+    /// Two digit years (the yy regex group) below this are 20yy, the rest 19yy
+    #[serde(default = "default_two_digit_year_pivot")]
+    pub two_digit_year_pivot: i64,
+    /// The time of day used in the prefix when all we know is the date
+    #[serde(default = "Time::midnight")]
+    pub date_only_time: Time,
+    /// A filename timestamp within this many minutes of a metadata candidate is close enough to
+    /// be the same moment, and the filename is used
+    #[serde(default = "default_close_match_minutes")]
+    pub close_match_minutes: f64,
+    /// How far off a whole number of hours (as a fraction of an hour) a filename timestamp can be
+    /// from a metadata candidate and still count as a time zone shift; the default is about 10
+    /// seconds
+    #[serde(default = "default_whole_hour_slack")]
+    pub whole_hour_slack: f64,
+    /// A whole-hour difference less than this is taken to be a nearby time zone, and the filename
+    /// is used
+    #[serde(default = "default_nearby_time_zone_hours")]
+    pub nearby_time_zone_hours: f64,
+    /// A whole-hour difference from nearby_time_zone_hours up to this is taken to be a filename in
+    /// UTC, and the metadata is used; anything bigger isn't a match at all
+    #[serde(default = "default_utc_filename_hours")]
+    pub utc_filename_hours: f64,
+    /// With nothing in the filename, the best metadata candidate wins if its score is at least
+    /// this many times the next best one's
+    #[serde(default = "default_score_dominance_ratio")]
+    pub score_dominance_ratio: u32,
+    /// Points added to the home time zone copy of a UTC metadata timestamp, since UTC is usually
+    /// bogus
+    #[serde(default = "default_utc_local_copy_bonus")]
+    pub utc_local_copy_bonus: u32,
+    /// Points taken off the copy of a metadata timestamp with no real time zone that assumes it
+    /// was really UTC
+    #[serde(default = "default_utc_copy_penalty")]
+    pub utc_copy_penalty: u32,
+    /// Two metadata timestamps a whole number of hours apart, up to this many, are taken to be the
+    /// same moment recorded in different time zones, and merged into one candidate
+    #[serde(default = "default_merge_hours")]
+    pub merge_hours: f64,
+    /// Points added to a metadata candidate on the same day as a date-only value
+    #[serde(default = "default_date_confirmed_bonus")]
+    pub date_confirmed_bonus: u32,
+    /// How long a file has to be left alone before `picnamion watch` handles it
+    #[serde(default = "default_watch_settle_seconds")]
+    pub watch_settle_seconds: u64,
+    /// A CSV or JSON file of timestamps and time zones for particular files; see overrides.rs
+    #[serde(default)]
+    pub overrides_file: Option<PathBuf>,
+    /// What overrides_file and the per-directory overrides files say, in the order they're tried;
+    /// filled in by Configuration
+    #[serde(skip)]
+    pub overrides: Vec<overrides::Override>,
+}
+
+impl Settings {
+    fn home_tz(&self) -> error_stack::Result<TimeZone, MyError> {
+        TimeZone::get(&self.home_time_zone)
+            .change_context_lazy(|| MyError::TimeZone(self.home_time_zone.clone()))
+            .attach_printable_lazy(|| format!("Bad home_time_zone {:?}", self.home_time_zone))
+    }
+
+    /// Checks that the matching tolerances make sense together
+    fn check_tolerances(&self) -> Result<(), String> {
+        if self.close_match_minutes.is_nan() || self.close_match_minutes < 0.0 {
+            return Err(format!(
+                "close_match_minutes must not be negative, not {}",
+                self.close_match_minutes
+            ));
+        }
+        if !(0.0..0.5).contains(&self.whole_hour_slack) {
+            return Err(format!(
+                "whole_hour_slack must be at least 0 and less than 0.5, not {}",
+                self.whole_hour_slack
+            ));
+        }
+        if self.merge_hours.is_nan() || self.merge_hours < 0.0 {
+            return Err(format!(
+                "merge_hours must not be negative, not {}",
+                self.merge_hours
+            ));
+        }
+        if !(0.0..=self.utc_filename_hours).contains(&self.nearby_time_zone_hours) {
+            return Err(format!(
+                "nearby_time_zone_hours ({}) must be between 0 and utc_filename_hours ({})",
+                self.nearby_time_zone_hours, self.utc_filename_hours
+            ));
+        }
+        Ok(())
+    }
+
+    fn forced_tz(&self) -> error_stack::Result<Option<TimeZone>, MyError> {
+        self.force_time_zone
+            .as_ref()
+            .map(|name| {
+                TimeZone::get(name)
+                    .change_context_lazy(|| MyError::TimeZone(name.clone()))
+                    .attach_printable_lazy(|| format!("Bad force_time_zone {:?}", name))
+            })
+            .transpose()
+    }
+}
+
+fn default_close_match_minutes() -> f64 {
+    10.0
+}
+
+fn default_whole_hour_slack() -> f64 {
+    0.003
+}
+
+fn default_nearby_time_zone_hours() -> f64 {
+    7.0
+}
+
+fn default_utc_filename_hours() -> f64 {
+    12.0
+}
+
+fn default_score_dominance_ratio() -> u32 {
+    2
+}
+
+fn default_utc_local_copy_bonus() -> u32 {
+    1
+}
+
+fn default_utc_copy_penalty() -> u32 {
+    1
+}
+
+fn default_merge_hours() -> f64 {
+    12.0
+}
+
+fn default_date_confirmed_bonus() -> u32 {
+    1
+}
+
+fn default_watch_settle_seconds() -> u64 {
+    10
+}
+
+fn default_two_digit_year_pivot() -> i64 {
+    70
+}
+
+fn default_home_time_zone() -> String {
+    "America/Los_Angeles".to_string()
+}
+
+fn default_exiftool_path() -> PathBuf {
+    "exiftool".into()
+}
+
+fn default_metadata_backend() -> String {
+    "auto".to_string()
+}
+
+fn default_sources() -> Vec<String> {
+    sources::DEFAULT_SOURCES.map(String::from).to_vec()
+}
+
+fn default_near_duplicate_distance() -> u32 {
+    6
+}
+
+/// The possible runtime environment for our application.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Environment {
+    Test,
+    Prod,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Test => "test",
+            Environment::Prod => "prod",
+        }
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "test" => Ok(Self::Test),
+            "production" => Ok(Self::Prod),
+            "prod" => Ok(Self::Prod),
+            other => Err(format!(
+                "{} is not a supported environment. Use either `test` or `prod`.",
+                other
+            )),
+        }
+    }
+}
+
+pub fn get_environment() -> Environment {
+    // Detect the running environment.
+    // Default to `prod` if unspecified.
+    let environment: Environment = std::env::var("PICNAMION_ENVIRONMENT")
+        .unwrap_or_else(|_| "prod".into())
+        .try_into()
+        .expect("Failed to parse PICNAMION_ENVIRONMENT.");
+
+    environment
+}
+
+/// Command line options that control what we do with a file once its prefix has been decided
+#[derive(Clone, Debug, Default)]
+struct Options {
+    /// Actually rename/move the file, rather than just reporting what we'd do
+    do_move: bool,
+    /// Stop at the first file that fails, rather than counting it and carrying on
+    fail_fast: bool,
+    /// Leave files decided with less confidence than this (see Basis::confidence) alone, and
+    /// list them at the end for a human to look at
+    min_confidence: u32,
+    /// Move files into a date-based directory tree instead of leaving them where they are
+    organize: Option<Organize>,
+    /// Move files we couldn't decide on here, each with a sidecar listing its choices
+    review_dir: Option<PathBuf>,
+}
+
+/// How a timestamp was decided on, from most to least trustworthy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Basis {
+    /// Someone said so, in an overrides file
+    Override,
+    /// A filename timestamp matched a metadata timestamp exactly
+    ExactMatch,
+    /// A filename timestamp was within a few minutes of a metadata timestamp
+    CloseMatch,
+    /// A filename timestamp and a metadata timestamp were a whole number of hours apart
+    HourShift,
+    /// There was exactly one metadata timestamp and nothing in the filename
+    SingleExif,
+    /// One metadata timestamp scored far better than the rest
+    ScoreDominance,
+    /// There was no metadata timestamp but there was exactly one filename timestamp
+    FilenameOnly,
+    /// Borrowed from a dated image elsewhere in the run that looks the same; `distance` is how
+    /// many bits their perceptual hashes differ by
+    NearDuplicate { distance: u32 },
+    /// Only the date was known; the time is the date_only_time setting
+    DateOnly,
+    /// Nothing but the file's own modification times
+    FileTime,
+}
+
+impl Basis {
+    /// How much to trust a timestamp decided on this way, from 0 to 100
+    pub fn confidence(&self) -> u32 {
+        match self {
+            Basis::Override => 100,
+            Basis::ExactMatch => 100,
+            Basis::CloseMatch => 90,
+            Basis::HourShift => 75,
+            Basis::SingleExif => 70,
+            Basis::ScoreDominance => 60,
+            Basis::FilenameOnly => 50,
+            // Identical pictures are as good as a filename; it drops off as they look less alike
+            Basis::NearDuplicate { distance } => 50u32.saturating_sub(distance * 5).max(10),
+            Basis::DateOnly => 20,
+            Basis::FileTime => 10,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// Not an image or video at all
+    NotMedia,
+    Decided {
+        ts: DateTime,
+        basis: Basis,
+    },
+    /// Too many plausible timestamps; a human has to pick
+    Undecided {
+        choices: Vec<Choice>,
+    },
+}
+
+/// One of the timestamps a human could pick for a file we couldn't decide on
+#[derive(Clone, Debug)]
+pub struct Choice {
+    pub ts: DateTime,
+    /// Where it came from: a metadata candidate's tags, or the filename regexes that found it
+    pub from: String,
+}
+
+/// Everything we worked out about a single file, before anything actually gets moved
+#[derive(Clone, Debug)]
+pub struct Decision {
+    pub filename: PathBuf,
+    mimetype: String,
+    /// Hash of the file contents, for finding duplicates; only calculated for images and videos
+    hash: Option<String>,
+    pub outcome: Outcome,
+    /// Every candidate timestamp the sources found, after clock_offset; empty when the file
+    /// wasn't media or an override decided it
+    pub candidates: Vec<Candidate>,
+    /// How we got to the outcome, step by step
+    trace: Vec<Step>,
+}
+
+fn handle_image(
+    image_path: &Path,
+    settings: &Settings,
+    sources: &[Box<dyn TimestampSource>],
+    backend: &mut dyn MetadataBackend,
+) -> error_stack::Result<Decision, MyError> {
+    // For messages; the file itself is always found by its real path, whatever bytes are in it
+    let filename = image_path.display();
+
+    // Read all metadata as a JSON Value (grouped by category); a backend that doesn't understand
+    // the file gives us nothing, which makes it not media
+    let metadata_json = backend.read(image_path)?.unwrap_or_default();
+
+    let mut trace: Vec<Step> = vec![Step::new(
+        "metadata",
+        format!("Read metadata with the {} backend", backend.name()),
+    )];
+
+    // Check for non-images
+    let not_media = |mimetype: String, mut trace: Vec<Step>| {
+        say!("ERROR: file {} is not an image.", filename);
+        trace.push(Step::new(
+            "not_media",
+            format!("MIME type {:?} is not an image or video", mimetype),
+        ));
+        Ok(Decision {
+            filename: image_path.to_path_buf(),
+            mimetype,
+            hash: None,
+            outcome: Outcome::NotMedia,
+            candidates: vec![],
+            trace,
+        })
+    };
+    let mimetype_str = metadata_json["File"]
+        .get("MIMEType")
+        .map_or_else(String::new, |mimetype| mimetype.to_string());
+    let is_media = mimetype_str.contains("video") || mimetype_str.contains("image");
+
+    // Only an override can date something that isn't media, so there's nothing more to do with
+    // it unless there might be one
+    let is_overrides = |source: &dyn TimestampSource| source.name() == "overrides";
+    if !is_media
+        && (settings.overrides.is_empty() || !sources.iter().any(|s| is_overrides(s.as_ref())))
+    {
+        return not_media(mimetype_str, trace);
+    }
+
+    // Media files are hashed to find copies of each other; anything else only if a hash: override
+    // gets as far as asking for it
+    let hash = if is_media {
+        OnceCell::from(
+            dedup::hash_file(image_path).change_context_lazy(|| MyError::ReadFile {
+                path: image_path.to_path_buf(),
+            })?,
+        )
+    } else {
+        OnceCell::new()
+    };
+
+    // println!("mdj: {:#?}", metadata_json);
+
+    // Filename regexes only see a UTF-8 version of the name, with anything else replaced by �
+    let lossy_filename = image_path.to_string_lossy();
+    let mut settings = Cow::Borrowed(settings);
+    let mut candidates: Vec<Candidate> = vec![];
+    for source in sources
+        .iter()
+        .filter(|source| is_media || is_overrides(source.as_ref()))
+    {
+        let input = SourceInput {
+            path: image_path,
+            filename: &lossy_filename,
+            metadata: &metadata_json,
+            hash: &hash,
+            settings: &settings,
+        };
+        let found = source.candidates(&input, &mut trace)?;
+        trace.push(Step::new(
+            "source",
+            format!(
+                "Source {} found {} candidate(s)",
+                source.name(),
+                found.len()
+            ),
+        ));
+        // A time zone override is home_time_zone from here on
+        let zone = found.iter().find_map(|candidate| match &candidate.time {
+            CandidateTime::HomeTimeZone(name) => Some(name.clone()),
+            _ => None,
+        });
+        if let Some(name) = zone {
+            settings = Cow::Owned(Settings {
+                home_time_zone: name,
+                ..settings.into_owned()
+            });
+        }
+        candidates.extend(found);
+
+        // An override timestamp beats everything else, so there's no need to ask the rest
+        if candidates
+            .iter()
+            .any(|candidate| matches!(candidate.time, CandidateTime::Override(_)))
+        {
+            break;
+        }
+    }
+    let settings = settings.as_ref();
+
+    let overridden = candidates
+        .iter()
+        .find_map(|candidate| match candidate.time {
+            CandidateTime::Override(datetime) => Some(datetime),
+            _ => None,
+        });
+    if let Some(ts) = overridden {
+        return Ok(decision_for(
+            image_path,
+            mimetype_str,
+            hash.into_inner(),
+            (ts, Basis::Override),
+            candidates,
+            trace,
+        ));
+    }
+    if !is_media {
+        return not_media(mimetype_str, trace);
+    }
+
+    let home_tz = settings.home_tz()?;
+    let forced_tz = settings.forced_tz()?;
+
+    // For a camera whose clock was set wrong; file system times are from some other clock
+    if let Some(offset) = settings.clock_offset {
+        for candidate in &mut candidates {
+            match &mut candidate.time {
+                CandidateTime::Metadata(timestamp) | CandidateTime::CivilZoned(timestamp) => {
+                    *timestamp = timestamp.checked_add(offset).change_context_lazy(|| {
+                        MyError::TimeArithmetic(format!("add clock_offset {offset} to {timestamp}"))
+                    })?
+                }
+                CandidateTime::Civil(datetime) => {
+                    *datetime = datetime.checked_add(offset).change_context_lazy(|| {
+                        MyError::TimeArithmetic(format!("add clock_offset {offset} to {datetime}"))
+                    })?
+                }
+                // Too coarse for a clock being off to matter
+                CandidateTime::Date(_) => {}
+                CandidateTime::FileTime(_) => {}
+                // Already dealt with
+                CandidateTime::Override(_) | CandidateTime::HomeTimeZone(_) => {}
+            }
+        }
+        trace.push(Step::new(
+            "clock_offset",
+            format!(
+                "Shifted metadata and filename timestamps by {}",
+                SpanPrinter::new()
+                    .direction(Direction::ForceSign)
+                    .duration_to_string(&offset)
+            ),
+        ));
+    }
+
+    // NOTE: We use a String for the hash key, even though Zoned would be far easier (and, indeed,
+    // it was previously implemented that way) because two Zoned values with different timestamps
+    // Eq the same, which doesn't work for our purposes.  In particular, when we see a UTC
+    // timestamp, we add a second timestamp in the local TZ as UTC is often but not always bogus.
+    // With Zoned we couldn't add both versions to exif_pic_timestamps.
+    // See the bogus TZ handling section just before we sort exif_pic_timestamps for that code.
+    let mut exif_pic_timestamps: HashMap<String, PicTimeStamp> = HashMap::new();
+    let mut exif_file_timestamp: Option<Zoned> = None;
+    let mut filename_timestamps: Vec<(DateTime, Option<Zoned>, String)> = vec![];
+    // From tags or filenames that only have the date
+    let mut dates: Vec<Date> = vec![];
+
+    let found = candidates.clone();
+
+    // Work through all the candidates, check that the metadata ones all match.
+    // Keep going with the matching one if found, otherwise bail.
+    for candidate in candidates {
+        let tag = Tag {
+            name: candidate.provenance,
+            score: candidate.score,
+        };
+
+        let timestamp = match candidate.time {
+            CandidateTime::Metadata(timestamp) => timestamp,
+            CandidateTime::FileTime(timestamp) => {
+                // Keep only the oldest of the file metedata based timestamps,
+                // since it's easy for file timestamps to became later but unlikely for
+                // them to become earlier than when they were really created
+                if exif_file_timestamp
+                    .as_ref()
+                    .is_none_or(|ts| &timestamp < ts)
+                {
+                    trace.push(
+                        Step::new("file_timestamp", "Earliest file system timestamp so far")
+                            .inputs([format!("{} = {}", tag.name, timestamp)]),
+                    );
+                    exif_file_timestamp = Some(timestamp);
+                }
+                continue;
+            }
+            CandidateTime::Civil(datetime) => {
+                let datetime = match &forced_tz {
+                    Some(forced) => datetime
+                        .to_zoned(home_tz.clone())
+                        .change_context_lazy(|| {
+                            MyError::TimeArithmetic(format!(
+                                "put {datetime} in {}",
+                                settings.home_time_zone
+                            ))
+                        })?
+                        .with_time_zone(forced.clone())
+                        .datetime(),
+                    None => datetime,
+                };
+                filename_timestamps.push((datetime, None, tag.name));
+                continue;
+            }
+            CandidateTime::CivilZoned(timestamp) => {
+                let timestamp = match &forced_tz {
+                    Some(forced) => timestamp.with_time_zone(forced.clone()),
+                    None => timestamp,
+                };
+                filename_timestamps.push((timestamp.datetime(), Some(timestamp), tag.name));
+                continue;
+            }
+            CandidateTime::Date(date) => {
+                trace.push(
+                    Step::new(
+                        "date_only_candidate",
+                        "Only a date, so it can't be matched like a full timestamp, but it can confirm or rule out candidates",
+                    )
+                    .inputs([format!("{} = {}", tag.name, date)]),
+                );
+                if !dates.contains(&date) {
+                    dates.push(date);
+                }
+                continue;
+            }
+            // Already dealt with
+            CandidateTime::Override(_) | CandidateTime::HomeTimeZone(_) => continue,
+        };
+        let input = format!("{} = {}", tag.name, timestamp);
+
+        if let Some(pts) = exif_pic_timestamps.get_mut(&timestamp.to_string()) {
+            pts.tags.push(tag);
+            pts.rescore();
+            trace.push(
+                Step::new("metadata_timestamp", "Same time as an existing candidate")
+                    .inputs([input])
+                    .rescored([pts.describe()]),
+            );
+        } else {
+            // We haven't stored this timestamp yet
+            if exif_pic_timestamps.is_empty() {
+                // We haven't stored *any* timestamps yet
+                let mut pts = PicTimeStamp {
+                    ts: timestamp.clone(),
+                    tags: vec![tag],
+                    score: 0,
+                };
+                pts.rescore();
+                trace.push(
+                    Step::new("metadata_timestamp", "New candidate")
+                        .inputs([input])
+                        .added([pts.describe()]),
+                );
+                exif_pic_timestamps.insert(timestamp.to_string(), pts);
+            } else {
+                // See if this is actually equivalent to some other timestamp
+                // by our standards
+                let mut new_ts = true;
+                for (ts_key, mut pts) in exif_pic_timestamps.clone() {
+                    let hours = (&pts.ts - &timestamp)
+                        .total(jiff::Unit::Hour)
+                        .change_context_lazy(|| {
+                            MyError::TimeArithmetic(format!(
+                                "work out the hours between {} and {timestamp}",
+                                pts.ts
+                            ))
+                        })?;
+                    if hours.fract() == 0.0 && hours <= settings.merge_hours {
+                        // Which one is better?
+                        let mut temp_pts = PicTimeStamp {
+                            ts: timestamp.clone(),
+                            tags: vec![tag.clone()],
+                            score: 0,
+                        };
+                        temp_pts.rescore();
+                        pts.rescore();
+
+                        let new_timestamp: Zoned = if temp_pts.score > pts.score
+                            || temp_pts.ts.offset() != tz::offset(-12)
+                        {
+                            temp_pts.ts
+                        } else {
+                            pts.ts.clone()
+                        };
+
+                        say!(
+                            "WARNING: TS {} and TS {} are exactly {} hours apart and hence are probably the same time in real life; adding it to the list for {}",
+                            ts_key,
+                            timestamp,
+                            hours,
+                            new_timestamp
+                        );
+
+                        new_ts = false;
+
+                        let old_description = pts.describe();
+                        pts.ts = new_timestamp.clone();
+                        pts.tags.push(tag.clone());
+                        pts.rescore();
+
+                        trace.push(
+                            Step::new(
+                                "twelve_hour_merge",
+                                format!(
+                                    "Exactly {} hours from an existing candidate, so probably the same moment; merged them",
+                                    hours
+                                ),
+                            )
+                            .inputs([input.clone()])
+                            .removed([old_description])
+                            .added([pts.describe()]),
+                        );
+
+                        exif_pic_timestamps.remove(&ts_key);
+                        exif_pic_timestamps.insert(new_timestamp.to_string(), pts);
+
+                        break;
+                    }
+                }
+
+                if new_ts {
+                    let mut pts = PicTimeStamp {
+                        ts: timestamp.clone(),
+                        tags: vec![tag],
+                        score: 0,
+                    };
+                    pts.rescore();
+                    trace.push(
+                        Step::new("metadata_timestamp", "New candidate")
+                            .inputs([input])
+                            .added([pts.describe()]),
+                    );
+                    exif_pic_timestamps.insert(timestamp.to_string(), pts);
+                }
+            }
+        }
+    }
+
+    if exif_pic_timestamps.is_empty()
+        && let Some(file_ts) = &exif_file_timestamp
+    {
+        // Stick the file-based timestamp in there; who knows, it might match
+        let pts = PicTimeStamp {
+            ts: file_ts.clone(),
+            tags: vec![Tag {
+                name: "File Earliest".to_string(),
+                score: 1,
+            }],
+            score: 1,
+        };
+        trace.push(
+            Step::new(
+                "file_earliest_candidate",
+                "No metadata timestamps, so trying the earliest file system timestamp",
+            )
+            .added([pts.describe()]),
+        );
+        exif_pic_timestamps.insert(file_ts.to_string(), pts);
+    }
+
+    // println!("epts before tz correction: {:#?}", exif_pic_timestamps);
+
+    // Force definitely bogus (-12) TZs to the home time zone
+    //
+    // For probably bogus (0) TZs, make a second copy with a higher value in the home time zone;
+    // if there's no matching filename timestamp this will lead to a human having to make a decision
+    for (ts_key, pts) in exif_pic_timestamps.clone().iter() {
+        if pts.ts.offset() == tz::offset(-12) {
+            exif_pic_timestamps.remove(ts_key);
+            let new_ts = pts
+                .ts
+                .datetime()
+                .to_zoned(home_tz.clone())
+                .change_context_lazy(|| {
+                    MyError::TimeArithmetic(format!(
+                        "put {} in {}",
+                        pts.ts.datetime(),
+                        settings.home_time_zone
+                    ))
+                })?;
+            let new_from_utc_ts = pts
+                .ts
+                .datetime()
+                .to_zoned(TimeZone::UTC)
+                .change_context_lazy(|| {
+                    MyError::TimeArithmetic(format!("put {} in UTC", pts.ts.datetime()))
+                })?
+                .with_time_zone(home_tz.clone());
+            say!(
+                "WARNING: Coerced exif timestamp to {} because it had no real time zone; before: {} after: {}.",
+                settings.home_time_zone,
+                ts_key,
+                new_ts
+            );
+            let mut new_pts = pts.clone();
+            new_pts.ts = new_ts.clone();
+            trace.push(
+                Step::new(
+                    "no_time_zone_coercion",
+                    format!(
+                        "Had no real time zone (-1200), so assumed {}",
+                        settings.home_time_zone
+                    ),
+                )
+                .removed([pts.describe()])
+                .added([new_pts.describe()]),
+            );
+            exif_pic_timestamps.insert(new_ts.to_string(), new_pts);
+
+            if !pts.is_file_earliest() {
+                say!(
+                    "WARNING: Also adding a timestamp copy that is shifted from UTC to {} because that is also a common issue, new copy is {}",
+                    settings.home_time_zone,
+                    new_from_utc_ts
+                );
+
+                let mut new_from_utc_pts = pts.clone();
+                new_from_utc_pts.ts = new_from_utc_ts.clone();
+                new_from_utc_pts.score = new_from_utc_pts
+                    .score
+                    .saturating_sub(settings.utc_copy_penalty);
+                trace.push(
+                    Step::new(
+                        "no_time_zone_utc_copy",
+                        format!(
+                            "Also added a copy that assumes it was really UTC, at {} less",
+                            points(settings.utc_copy_penalty)
+                        ),
+                    )
+                    .inputs([pts.describe()])
+                    .added([new_from_utc_pts.describe()]),
+                );
+                exif_pic_timestamps.insert(new_from_utc_ts.to_string(), new_from_utc_pts);
+            }
+        }
+        if pts.ts.offset() == tz::offset(0) {
+            let new_ts = pts.ts.with_time_zone(home_tz.clone());
+            say!(
+                "WARNING: Added a copied exif timestamp in {} because UTC is usually bogus; original: {} new one: {}",
+                settings.home_time_zone,
+                ts_key,
+                new_ts
+            );
+            let mut new_pts = pts.clone();
+            new_pts.ts = new_ts.clone();
+            new_pts.rescore();
+            new_pts.score += settings.utc_local_copy_bonus;
+            trace.push(
+                Step::new(
+                    "utc_local_copy",
+                    format!(
+                        "UTC is usually bogus, so added a copy in {} at {} more",
+                        settings.home_time_zone,
+                        points(settings.utc_local_copy_bonus)
+                    ),
+                )
+                .inputs([pts.describe()])
+                .added([new_pts.describe()]),
+            );
+            exif_pic_timestamps.insert(new_ts.to_string(), new_pts);
+        }
+    }
+
+    if let Some(forced) = &forced_tz {
+        let mut forced_ptses: HashMap<String, PicTimeStamp> = HashMap::new();
+        for pts in exif_pic_timestamps.into_values() {
+            let mut new_pts = pts.clone();
+            new_pts.ts = pts.ts.with_time_zone(forced.clone());
+            // Copies of the same moment (like the UTC and home time ones above) are now the same
+            // candidate; keep the better one
+            let key = new_pts.ts.to_string();
+            if forced_ptses
+                .get(&key)
+                .is_none_or(|existing| existing.score < new_pts.score)
+            {
+                forced_ptses.insert(key, new_pts);
+            }
+        }
+        trace.push(
+            Step::new(
+                "force_time_zone",
+                format!(
+                    "Converted metadata candidates to {}",
+                    settings.force_time_zone.as_deref().unwrap_or_default()
+                ),
+            )
+            .added(forced_ptses.values().map(PicTimeStamp::describe)),
+        );
+        exif_pic_timestamps = forced_ptses;
+    }
+
+    // println!("epts after tz correction: {:#?}", exif_pic_timestamps);
+
+    // The timestamp we've settled on, if any, and how we got there; the prefix is built from this
+    // at the end
+    let mut decided: Option<(DateTime, Basis)> = None;
+
+    // The into_values here is on purpose because we don't want anyone using exif_pic_timestamps
+    // after this
+    let mut sorted_ptses = exif_pic_timestamps.into_values().collect::<Vec<_>>();
+
+    // A date on its own is still good evidence for which day it was: candidates on that day get
+    // another point, and ones more than a day away from it (allowing for time zones) are out
+    if !dates.is_empty() {
+        let mut vetoed = vec![];
+        for pts in sorted_ptses
+            .iter_mut()
+            .filter(|pts| !pts.is_file_earliest())
+        {
+            let day = pts.ts.date();
+            if dates.contains(&day) {
+                pts.score += settings.date_confirmed_bonus;
+                trace.push(
+                    Step::new(
+                        "date_confirmed",
+                        format!(
+                            "On the same day as a date-only value, so {} more",
+                            points(settings.date_confirmed_bonus)
+                        ),
+                    )
+                    .rescored([pts.describe()]),
+                );
+            } else if dates
+                .iter()
+                .all(|date| day.since(*date).is_ok_and(|span| span.get_days().abs() > 1))
+            {
+                vetoed.push(pts.ts.to_string());
+                trace.push(
+                    Step::new(
+                        "date_vetoed",
+                        "More than a day away from every date-only value",
+                    )
+                    .removed([pts.describe()]),
+                );
+            }
+        }
+        sorted_ptses.retain(|pts| !vetoed.contains(&pts.ts.to_string()));
+    }
+
+    // Ties go to the earlier timestamp, so the order (and so the choices offered) doesn't depend
+    // on the hash map's
+    sorted_ptses.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.ts.cmp(&b.ts)));
+
+    // Walk through every distinct filename timestamp, in priority order (source order, then the
+    // order of the regexes in the configuration), looking for one that matches the exif
+    // timestamps; the first to match wins
+    //
+    // Since the file prefix has no TZ (ooops), when we're using the exif timestamp as the
+    // authoritative value, we *could* convert the prefix value to the home time zone for
+    // consistency, but since by definition anything in some other time zone has TZ info in the
+    // metadata, we'll just leave it as is and someone can check the metadata if they want TZ info.
+
+    let filename_matches = FilenameMatch::gather(filename_timestamps);
+    for file_match in &filename_matches {
+        trace.push(
+            Step::new(
+                "filename_timestamp",
+                match file_match.regexes.len() {
+                    1 => "A filename regex matched".to_string(),
+                    n => format!("{n} filename regexes matched with the same timestamp"),
+                },
+            )
+            .inputs(file_match.regexes.clone())
+            .added([file_match.to_string()]),
+        );
+    }
+
+    for file_match in &filename_matches {
+        let regex_dt = file_match.datetime;
+        if decided.is_none() {
+            // First check for exact or near-exact matches; a filename that says which time zone
+            // it's in is compared by the moment it means, and anything else by wall clock time
+            for exif_pts in sorted_ptses.clone() {
+                if decided.is_none() {
+                    let exif_ts = exif_pts.ts.clone();
+                    // Only one of them might have fractions of a second
+                    let exact = match &file_match.zoned {
+                        Some(zoned) => {
+                            zoned.timestamp().as_second() == exif_ts.timestamp().as_second()
+                        }
+                        None => to_the_second(regex_dt) == to_the_second(exif_ts.datetime()),
+                    };
+                    if exact {
+                        say!(
+                            "INFO: Exact match between filename timestamp {} and exif timestmap {}.",
+                            file_match,
+                            exif_ts
+                        );
+                        trace.push(
+                            Step::new(
+                                "exact_match",
+                                "Filename timestamp is exactly the same as a metadata candidate",
+                            )
+                            .inputs([file_match.to_string(), exif_pts.describe()]),
+                        );
+                        decided = Some((regex_dt, Basis::ExactMatch));
+                    } else {
+                        let minutes = match &file_match.zoned {
+                            Some(zoned) => zoned.duration_since(&exif_ts).as_secs_f64() / 60.0,
+                            None => (regex_dt - exif_ts.datetime())
+                                .total((
+                                    jiff::Unit::Minute,
+                                    jiff::SpanRelativeTo::days_are_24_hours(),
+                                ))
+                                .change_context_lazy(|| {
+                                    MyError::TimeArithmetic(format!(
+                                        "work out the minutes between {regex_dt} and {exif_ts}"
+                                    ))
+                                })?,
+                        }
+                        .abs();
+                        if minutes < settings.close_match_minutes {
+                            say!(
+                                "INFO: Close enough match between filename timestamp {} and exif timestmap {}, {}, {}.",
+                                regex_dt,
+                                exif_ts,
+                                exif_ts.datetime(),
+                                minutes
+                            );
+                            trace.push(
+                                Step::new(
+                                    "close_match",
+                                    format!(
+                                        "Filename timestamp is within {} minutes ({:.2}) of a metadata candidate; using the filename",
+                                        settings.close_match_minutes, minutes
+                                    ),
+                                )
+                                .inputs([file_match.to_string(), exif_pts.describe()]),
+                            );
+                            decided = Some((regex_dt, Basis::CloseMatch));
+                        }
+                    }
+                }
+            }
+
+            // Now check for looser matches.  Being a whole number of hours off is how a wall
+            // clock in the wrong time zone shows up, which a filename with its time zone can't be.
+            if file_match.zoned.is_some() {
+                continue;
+            }
+            for exif_pts in sorted_ptses.clone() {
+                if decided.is_none() {
+                    let exif_ts = exif_pts.ts.clone();
+                    // To the second, like the exact match, so fractions of a second in the
+                    // filename don't leave it just short of a whole hour
+                    let hours = (to_the_second(regex_dt) - exif_ts.datetime())
+                        .total((jiff::Unit::Hour, jiff::SpanRelativeTo::days_are_24_hours()))
+                        .change_context_lazy(|| {
+                            MyError::TimeArithmetic(format!(
+                                "work out the hours between {regex_dt} and {exif_ts}"
+                            ))
+                        })?
+                        .abs();
+                    let inputs = [regex_dt.to_string(), exif_pts.describe()];
+                    // By default this allows a variance of about 10 seconds
+                    if hours.fract() <= settings.whole_hour_slack {
+                        if hours < settings.nearby_time_zone_hours {
+                            say!(
+                                "WARNING: filename timestamp {} is exactly (give or take a few seconds) {} hours off from exif timestamp {}, which is less than {}, so we're assuming that the picture was taken in another nearby time zone and treating the filename value as correct.",
+                                regex_dt,
+                                hours,
+                                exif_ts,
+                                settings.nearby_time_zone_hours
+                            );
+                            trace.push(
+                                Step::new(
+                                    "whole_hour_match",
+                                    format!(
+                                        "Filename timestamp is a whole {} hours off a metadata candidate, less than {}, so probably a nearby time zone; using the filename",
+                                        hours.round(),
+                                        settings.nearby_time_zone_hours
+                                    ),
+                                )
+                                .inputs(inputs),
+                            );
+                            decided = Some((regex_dt, Basis::HourShift));
+                        } else if hours <= settings.utc_filename_hours {
+                            say!(
+                                "WARNING: filename timestamp {} is exactly (give or take a few seconds) {} hours off from exif timestamp {}, which is at least {} but no more than {}, so we're assuming that the filename value is it UTC or something, and using the exif value.",
+                                regex_dt,
+                                hours,
+                                exif_ts,
+                                settings.nearby_time_zone_hours,
+                                settings.utc_filename_hours
+                            );
+                            trace.push(
+                                Step::new(
+                                    "whole_hour_match",
+                                    format!(
+                                        "Filename timestamp is a whole {} hours off a metadata candidate, {} to {}, so the filename is probably UTC; using the metadata",
+                                        hours.round(),
+                                        settings.nearby_time_zone_hours,
+                                        settings.utc_filename_hours
+                                    ),
+                                )
+                                .inputs(inputs),
+                            );
+                            decided = Some((exif_ts.datetime(), Basis::HourShift));
+                        } else {
+                            say!(
+                                "WARNING: filename timestamp {} is exactly (give or take a few seconds) {} hours off from exif timestamp {}, which is more than {} hours, ignoring that they might be time zone shifted and treating this as not a match.",
+                                regex_dt,
+                                hours,
+                                exif_ts,
+                                settings.utc_filename_hours
+                            );
+                            trace.push(
+                                Step::new(
+                                    "whole_hour_mismatch",
+                                    format!(
+                                        "Filename timestamp is a whole {} hours off a metadata candidate, more than {}, so not treated as a match",
+                                        hours.round(),
+                                        settings.utc_filename_hours
+                                    ),
+                                )
+                                .inputs(inputs),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // println!("sorted_ptses before: {:#?}", sorted_ptses);
+
+    // If it didn't match above, the "File Earliest" timestamp is no longer interesting, drop it
+    if decided.is_none()
+        && let Some(file_earliest) = sorted_ptses.iter().find(|x| x.is_file_earliest())
+    {
+        trace.push(
+            Step::new(
+                "drop_file_earliest",
+                "The file system timestamp didn't match anything in the filename",
+            )
+            .removed([file_earliest.describe()]),
+        );
+    }
+    sorted_ptses.retain(|x| !x.is_file_earliest());
+
+    say!("sorted_ptses: {:#?}", sorted_ptses);
+
+    if filename_matches.is_empty() {
+        say!(
+            "WARNING: no timestamp info was found in the filename {} at all; falling back to the exif data.",
+            filename
+        );
+
+        // Here's where we pick the best of the exif-based timestamps.
+        if sorted_ptses.is_empty()
+            && dates.is_empty()
+            && let Some(file_ts) = &exif_file_timestamp
+        {
+            say!(
+                "WARNING: No real timestamps, taking oldest file timestamp: {:#?}",
+                exif_file_timestamp
+            );
+            trace.push(Step::new(
+                "file_time_fallback",
+                "Nothing in the filename or metadata; using the earliest file system timestamp",
+            ));
+            decided = Some((file_ts.datetime(), Basis::FileTime));
+        } else if sorted_ptses.len() == 1 {
+            trace.push(
+                Step::new(
+                    "single_candidate",
+                    "Nothing in the filename and exactly one metadata candidate",
+                )
+                .inputs([sorted_ptses[0].describe()]),
+            );
+            decided = Some((sorted_ptses[0].ts.datetime(), Basis::SingleExif));
+        } else if sorted_ptses.len() > 1 {
+            let first_pts = sorted_ptses[0].clone();
+            let second_pts = sorted_ptses[1].clone();
+            let inputs = [first_pts.describe(), second_pts.describe()];
+
+            if first_pts.score >= (second_pts.score * settings.score_dominance_ratio) {
+                say!(
+                    "WARNING: Picking best timestamp by score:\n{:#?}\n\nvs.\n{:#?}\n\n",
+                    first_pts,
+                    second_pts
+                );
+                trace.push(
+                    Step::new(
+                        "score_dominance",
+                        format!(
+                            "Nothing in the filename; the best metadata candidate scored at least {} times the next best",
+                            settings.score_dominance_ratio
+                        ),
+                    )
+                    .inputs(inputs),
+                );
+                decided = Some((first_pts.ts.datetime(), Basis::ScoreDominance));
+            } else {
+                say!(
+                    "ERROR: Too many possibly-valid timestamps, not enough score difference between first and second; can't select a prefix."
+                );
+                trace.push(
+                    Step::new(
+                        "score_too_close",
+                        format!(
+                            "Nothing in the filename; the best metadata candidate scored less than {} times the next best",
+                            settings.score_dominance_ratio
+                        ),
+                    )
+                    .inputs(inputs),
+                );
+            }
+        }
+    }
+
+    if decided.is_none() {
+        // If there are no non-file-based exif timestamps, and there's a filename timestamp,
+        // use the latter
+        if sorted_ptses.is_empty() && filename_matches.len() == 1 {
+            say!(
+                "WARNING: No non-file-based exif timestamp found, but a filename timestamp exists, so using the latter."
+            );
+            trace.push(
+                Step::new(
+                    "filename_only",
+                    "No metadata candidates and exactly one filename timestamp",
+                )
+                .inputs([filename_matches[0].datetime.to_string()]),
+            );
+            decided = Some((filename_matches[0].datetime, Basis::FilenameOnly));
+        } else if sorted_ptses.is_empty() && filename_matches.is_empty() && dates.len() == 1 {
+            say!(
+                "WARNING: Only a date was found, so using it with the placeholder time {}.",
+                settings.date_only_time
+            );
+            trace.push(
+                Step::new(
+                    "date_only",
+                    "Nothing but a date; using it with the date_only_time placeholder",
+                )
+                .inputs([dates[0].to_string()]),
+            );
+            decided = Some((
+                dates[0].to_datetime(settings.date_only_time),
+                Basis::DateOnly,
+            ));
+        } else {
+            // Dates on their own are only choices when there's nothing better; otherwise they've
+            // already had their say, confirming or ruling out the other candidates
+            let date_choices: Vec<Choice> =
+                if sorted_ptses.is_empty() && filename_matches.is_empty() {
+                    dates
+                        .iter()
+                        .map(|date| Choice {
+                            ts: date.to_datetime(settings.date_only_time),
+                            from: format!(
+                            "date only, {}",
+                            found
+                                .iter()
+                                .filter(|candidate| {
+                                    matches!(candidate.time, CandidateTime::Date(d) if d == *date)
+                                })
+                                .map(|candidate| candidate.provenance.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                };
+
+            let mut output = "".to_owned();
+            for ept in sorted_ptses.clone() {
+                let newpath = prefixed_path(image_path, &prefix_for(ept.ts.datetime()));
+                output += &format!("{}:\nmv '{}' '{}'\n\n", ept.ts, filename, newpath.display());
+            }
+            for file_match in &filename_matches {
+                let aft = file_match.datetime;
+                let newpath = prefixed_path(image_path, &prefix_for(aft));
+                output += &format!("{}:\nmv '{}' '{}'\n\n", aft, filename, newpath.display());
+            }
+            for choice in &date_choices {
+                let newpath = prefixed_path(image_path, &prefix_for(choice.ts));
+                output += &format!(
+                    "{}:\nmv '{}' '{}'\n\n",
+                    choice.ts,
+                    filename,
+                    newpath.display()
+                );
+            }
+
+            say!(
+                "ERROR: Unable to decide on an acceptable prefix for file {}\n\nHere's all exif timestamps {:#?}\n\nAnd here's all the file timestamps we matched: {:#?}\n\nand here's the right command for each option:\n\n{}",
+                filename,
+                sorted_ptses,
+                filename_matches,
+                output,
+            );
+
+            trace.push(
+                Step::new(
+                    "undecided",
+                    "No rule picked a single timestamp; a human has to choose",
+                )
+                .inputs(
+                    sorted_ptses
+                        .iter()
+                        .map(|pts| pts.describe())
+                        .chain(filename_matches.iter().map(|m| m.datetime.to_string()))
+                        .chain(date_choices.iter().map(|choice| choice.ts.to_string())),
+                ),
+            );
+
+            // Not an error as such, since we don't want to stop processing further files; the
+            // summary counts it as ambiguous, and --fail-on ambiguous makes that fail the run.
+            return Ok(Decision {
+                filename: image_path.to_path_buf(),
+                mimetype: mimetype_str,
+                hash: hash.into_inner(),
+                outcome: Outcome::Undecided {
+                    choices: sorted_ptses
+                        .iter()
+                        .map(|pts| Choice {
+                            ts: pts.ts.datetime(),
+                            from: pts.describe(),
+                        })
+                        .chain(filename_matches.iter().map(|file_match| Choice {
+                            ts: file_match.datetime,
+                            from: format!("filename, {}", file_match.regexes.join(", ")),
+                        }))
+                        .chain(date_choices)
+                        .collect(),
+                },
+                candidates: found,
+                trace,
+            });
+        }
+    }
+
+    Ok(decision_for(
+        image_path,
+        mimetype_str,
+        hash.into_inner(),
+        decided.unwrap(),
+        found,
+        trace,
+    ))
+}
+
+/// The decision for a file once its timestamp has been worked out
+fn decision_for(
+    image_path: &Path,
+    mimetype: String,
+    hash: Option<String>,
+    (ts, basis): (DateTime, Basis),
+    candidates: Vec<Candidate>,
+    mut trace: Vec<Step>,
+) -> Decision {
+    say!("INFO: Prefix determined: {}", prefix_for(ts));
+    trace.push(Step::new(
+        "decided",
+        format!(
+            "Prefix {} ({:?}, confidence {})",
+            prefix_for(ts),
+            basis,
+            basis.confidence()
+        ),
+    ));
+
+    Decision {
+        filename: image_path.to_path_buf(),
+        mimetype,
+        hash,
+        outcome: Outcome::Decided { ts, basis },
+        candidates,
+        trace,
+    }
+}
+
+/// "one point" or "3 points", for decision traces
+fn points(n: u32) -> String {
+    match n {
+        1 => "one point".to_string(),
+        n => format!("{n} points"),
+    }
+}
+
+/// The file's path with the prefix added to its name
+fn prefixed_path(filepath: &Path, prefix: &str) -> PathBuf {
+    filepath.with_file_name(organize::prefixed_name(filepath, prefix))
+}
+
+/// Drops any fraction of a second
+fn to_the_second(dt: DateTime) -> DateTime {
+    dt.with()
+        .subsec_nanosecond(0)
+        .build()
+        .expect("a whole second is always valid")
+}
+
+pub fn prefix_for(ts: DateTime) -> String {
+    ts.strftime("%Y-%m-%d_%H-%M-%S--").to_string()
+}
+
+/// Renames or moves a file according to its decision; `duplicate` means it's a spare copy of some
+/// other file in this run and should go into a duplicates directory instead.
+fn apply_decision(
+    decision: &Decision,
+    options: &Options,
+    duplicate: bool,
+) -> error_stack::Result<FileResult, MyError> {
+    let ts = match decision.outcome {
+        Outcome::Decided { ts, .. } => ts,
+        Outcome::NotMedia => return Ok(FileResult::NotMedia),
+        Outcome::Undecided { .. } => {
+            return match &options.review_dir {
+                Some(review_dir) => review::quarantine(
+                    decision,
+                    review_dir,
+                    "no single timestamp could be picked",
+                    options.do_move,
+                ),
+                None => Ok(FileResult::Ambiguous),
+            };
+        }
+    };
+    let filepath = decision.filename.as_path();
+    let prefix = prefix_for(ts);
+
+    let mut newpath = match &options.organize {
+        Some(organize) => organize.dest_path(ts, filepath, &prefix),
+        None => prefixed_path(filepath, &prefix),
+    };
+
+    if duplicate && let Some(name) = newpath.file_name().map(|name| name.to_owned()) {
+        newpath.pop();
+        newpath.push("duplicates");
+        newpath.push(name);
+    }
+
+    let moving_elsewhere = options.organize.is_some() || duplicate;
+
+    if newpath.exists() {
+        println!(
+            "ERROR: Not moving file {} because {} already exists.",
+            filepath.display(),
+            newpath.display()
+        );
+        return Ok(FileResult::Collision);
+    }
+
+    if !do_move_or_report(filepath, &newpath, options.do_move, moving_elsewhere)? {
+        return Ok(FileResult::DryRun);
+    }
+
+    if decision.mimetype.contains("video") {
+        // FIXME: Even compared to other stuff here, this is incredibly specific to my setup; if
+        // anyone else is using this, tell me and I'll figure out a way to make this optional or
+        // configurable or something.
+        let output = Command::new("/home/rlpowell/bin/video_hard_rotate.sh")
+            .arg(&newpath)
+            .output()
+            .change_context_lazy(|| MyError::Rotate {
+                path: newpath.clone(),
+            })?;
+
+        println!(
+            "video_hard_rotate.sh output: {}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    Ok(FileResult::Renamed)
+}
+
+/// Moves the file to its new name if we've been asked to, otherwise just says where it would go
+/// when that's not obvious from the prefix alone.  `elsewhere` means it's going to a different
+/// directory, not just getting a prefix.  Returns whether the file was actually moved.
+fn do_move_or_report(
+    filepath: &Path,
+    newpath: &Path,
+    do_move: bool,
+    elsewhere: bool,
+) -> error_stack::Result<bool, MyError> {
+    if !do_move {
+        if elsewhere {
+            println!(
+                "INFO: Would move file {} to {}",
+                filepath.display(),
+                newpath.display()
+            );
+        }
+        return Ok(false);
+    }
+
+    println!(
+        "INFO: Moving file {} to {}",
+        filepath.display(),
+        newpath.display()
+    );
+    let moved = if elsewhere {
+        organize::move_file(filepath, newpath)
+    } else {
+        fs::rename(filepath, newpath)
+    };
+    moved.change_context_lazy(|| MyError::Move {
+        from: filepath.to_path_buf(),
+        to: newpath.to_path_buf(),
+    })?;
+
+    Ok(true)
+}
+
+/// `picnamion explain [--json] FILE...`: shows, step by step, how the decision for each file was
+/// reached, without moving anything.  The files are explained as one run, so copies of each other
+/// among them share decisions just as they would in a run.
+fn explain(
+    args: impl Iterator<Item = OsString>,
+    configuration: &Configuration,
+    settings: &Settings,
+    backend: &mut dyn MetadataBackend,
+) -> error_stack::Result<(), MyError> {
+    let mut json = false;
+    let mut files: Vec<PathBuf> = vec![];
+    for arg in args {
+        match arg.to_str() {
+            Some("--json") => json = true,
+            _ => files.push(arg.into()),
+        }
+    }
+
+    if files.is_empty() {
+        panic!("explain needs at least one file.");
+    }
+
+    QUIET.store(true, Ordering::Relaxed);
+
+    let mut decisions = files
+        .iter()
+        .map(|file| decide(configuration, file, backend))
+        .collect::<error_stack::Result<Vec<_>, MyError>>()?;
+    // As in a run, files that already have a prefix can lend it to undated near-duplicates
+    let earlier: Vec<(PathBuf, DateTime)> = files
+        .iter()
+        .filter_map(|file| prefix_time(file).map(|ts| (file.clone(), ts)))
+        .collect();
+    dedup::share_decisions(&mut decisions);
+    phash::share_near_duplicates(&mut decisions, &earlier, settings.near_duplicate_distance);
+
+    for (index, decision) in decisions.iter().enumerate() {
+        if json {
+            println!("{}", trace::to_json(decision));
+        } else {
+            if index > 0 {
+                println!();
+            }
+            print!("{}", trace::to_text(decision));
+        }
+    }
+
+    Ok(())
+}
+
+/// Works out the decision for one file, the way a run does, without moving anything
+pub fn decide(
+    configuration: &Configuration,
+    path: &Path,
+    backend: &mut dyn MetadataBackend,
+) -> error_stack::Result<Decision, MyError> {
+    let (settings, sources) = settings_for_file(configuration, path);
+    handle_image(path, &settings, &sources, backend)
+}
+
+/// The settings and timestamp sources for a file, which .picnamion.json5 files in its directory
+/// or above can change
+fn settings_for_file(
+    configuration: &Configuration,
+    path: &Path,
+) -> (Rc<Settings>, Vec<Box<dyn TimestampSource>>) {
+    let filename = path.display();
+    let settings = configuration
+        .settings_for_file(path)
+        .unwrap_or_else(|e| panic!("Failed to read configuration for {filename}: {e}"));
+    let sources = sources::from_settings(&settings)
+        .unwrap_or_else(|e| panic!("Failed to read configuration for {filename}: {e}"));
+    settings
+        .check_tolerances()
+        .unwrap_or_else(|e| panic!("Failed to read configuration for {filename}: {e}"));
+    (settings, sources)
+}
+
+/// `picnamion config show [--origin] [DIR]`: prints the effective settings for files in DIR
+/// (default: the current directory), and with --origin which configuration layer each one came
+/// from.  `picnamion config builtins` lists the built-in filename patterns.
+fn config_command(
+    mut args: impl Iterator<Item = OsString>,
+    configuration: &Configuration,
+) -> error_stack::Result<(), MyError> {
+    match args.next().as_deref().and_then(|arg| arg.to_str()) {
+        Some("show") => {}
+        Some("builtins") => {
+            println!("Built-in filename patterns, version {}:", patterns::VERSION);
+            for builtin in patterns::BUILTINS {
+                println!(
+                    "  builtin:{:<20} {}, like {:?}",
+                    builtin.name, builtin.description, builtin.examples[0].0
+                );
+            }
+            return Ok(());
+        }
+        _ => panic!("The config commands are: config show [--origin] [DIR], config builtins"),
+    }
+    let mut origin = false;
+    let mut dir = PathBuf::from(".");
+    for arg in args {
+        match arg.to_str() {
+            Some("--origin") => origin = true,
+            _ => dir = arg.into(),
+        }
+    }
+
+    let settings = configuration
+        .show(&dir)
+        .change_context(MyError::Configuration)?;
+    for (key, value, from) in settings {
+        if origin {
+            println!("{key} = {value}    # from {from}");
+        } else {
+            println!("{key} = {value}");
+        }
+    }
+
+    Ok(())
+}
+
+/// The whole command line program; see the README for what it does
+pub fn run() -> error_stack::Result<(), MyError> {
+    // --set KEY=VALUE can go anywhere, and applies to everything
+    let mut overrides: Vec<(String, String)> = vec![];
+    // File names can be any bytes at all, so arguments stay OsStrings until we know they're
+    // something else
+    let mut all_args: Vec<OsString> = vec![];
+    let mut raw_args = env::args_os().skip(1);
+    while let Some(arg) = raw_args.next() {
+        if arg == "--set" {
+            let setting = utf8_arg(raw_args.next(), "--set needs a KEY=VALUE argument");
+            let (key, value) = setting
+                .split_once('=')
+                .unwrap_or_else(|| panic!("--set needs a KEY=VALUE argument, not {setting}"));
+            overrides.push((key.to_string(), value.to_string()));
+        } else {
+            all_args.push(arg);
+        }
+    }
+
+    let configuration = Configuration::discover(&overrides);
+    let mut args = all_args.into_iter().peekable();
+
+    if args.peek().and_then(|arg| arg.to_str()) == Some("config") {
+        args.next();
+        return config_command(args, &configuration);
+    }
+
+    if args.peek().and_then(|arg| arg.to_str()) == Some("resolve") {
+        args.next();
+        return review::run(args);
+    }
+
+    if args.peek().and_then(|arg| arg.to_str()) == Some("check-config") {
+        args.next();
+        let dir = PathBuf::from(args.next().unwrap_or_else(|| ".".into()));
+        if check_config::run(&configuration, &dir) > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Settings for the whole run come from the current directory; the ones that matter per file
+    // are looked up for each file
+    let (settings, _) = settings_for_file(&configuration, Path::new(""));
+    // println!("Settings: {:#?}", settings);
+
+    let mut backend = metadata::from_settings(&settings)
+        .unwrap_or_else(|e| panic!("Failed to read configuration: {e}"));
+
+    if args.peek().and_then(|arg| arg.to_str()) == Some("explain") {
+        args.next();
+        return explain(args, &configuration, &settings, backend.as_mut());
+    }
+
+    if args.peek().and_then(|arg| arg.to_str()) == Some("watch") {
+        args.next();
+        return watch::run(args, &configuration, backend.as_mut());
+    }
+
+    let mut options = Options::default();
+    let mut files: Vec<PathBuf> = vec![];
+
+    let mut organize_template: Option<String> = None;
+    let mut organize_root = PathBuf::new();
+    let mut keep_name = false;
+    let mut move_duplicates = false;
+    let mut fail_on: Vec<FailOn> = vec![];
+    let mut files_from: Vec<PathBuf> = vec![];
+    let mut null_separated = false;
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            // Actually do the move
+            Some("-m") => options.do_move = true,
+            Some("--organize") => {
+                organize_template = Some(utf8_arg(
+                    args.next(),
+                    "--organize needs a template argument, like {year}/{month}",
+                ))
+            }
+            Some("--organize-root") => {
+                organize_root = args
+                    .next()
+                    .expect("--organize-root needs a directory argument")
+                    .into()
+            }
+            Some("--keep-name") => keep_name = true,
+            Some("--fail-fast") => options.fail_fast = true,
+            Some("--fail-on") => {
+                let classes = utf8_arg(args.next(), "--fail-on needs an argument, like ambiguous");
+                for class in classes.split(',') {
+                    fail_on.push(FailOn::parse(class).unwrap_or_else(|e| panic!("{e}")));
+                }
+            }
+            Some("--min-confidence") => {
+                options.min_confidence =
+                    utf8_arg(args.next(), "--min-confidence needs a number from 0 to 100")
+                        .parse()
+                        .ok()
+                        .filter(|n| *n <= 100)
+                        .expect("--min-confidence needs a number from 0 to 100")
+            }
+            Some("--move-duplicates") => move_duplicates = true,
+            Some("--review-dir") => {
+                options.review_dir = Some(
+                    args.next()
+                        .expect("--review-dir needs a directory argument")
+                        .into(),
+                )
+            }
+            Some("--files-from") => files_from.push(
+                args.next()
+                    .expect("--files-from needs a file argument, or - for stdin")
+                    .into(),
+            ),
+            Some("-0" | "--null") => null_separated = true,
+            _ => files.push(arg.into()),
+        }
+    }
+
+    if let Some(template) = organize_template {
+        options.organize = Some(
+            Organize::new(&template, organize_root, keep_name)
+                .unwrap_or_else(|e| panic!("Bad --organize template: {e}")),
+        );
+    } else if keep_name {
+        panic!("--keep-name only makes sense with --organize.");
+    }
+
+    for list in files_from {
+        files.extend(
+            read_file_list(&list, null_separated)
+                .unwrap_or_else(|e| panic!("Failed to read --files-from {}: {e}", list.display())),
+        );
+    }
+
+    if files.is_empty() {
+        panic!("Need at least one argument of files/directories to check.");
+    }
+
+    let mut decisions: Vec<Decision> = vec![];
+    // Files renamed by earlier runs, for undated near-duplicates to borrow timestamps from
+    let mut earlier: Vec<(PathBuf, DateTime)> = vec![];
+    let mut summary = Summary::default();
+
+    for file in files {
+        let paths: Vec<PathBuf> = if file.is_file() {
+            vec![file]
+        } else {
+            // NUL separated, since a file name can have anything else in it, newlines included
+            let output = Command::new("find")
+                .arg(file)
+                .arg("-type")
+                .arg("f")
+                .arg("-print0")
+                .output()
+                .change_context(MyError::Command("find".to_string()))?;
+
+            let (prefixed, unprefixed): (Vec<PathBuf>, Vec<PathBuf>) = output
+                .stdout
+                .split(|byte| *byte == 0)
+                .filter(|path| !path.is_empty())
+                .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+                .partition(|path| already_prefixed(path));
+            for path in prefixed {
+                summary.add(FileResult::AlreadyPrefixed);
+                if let Some(ts) = prefix_time(&path) {
+                    earlier.push((path, ts));
+                }
+            }
+            unprefixed
+        };
+
+        for path in paths {
+            println!("\n\n********************** path: {}\n", path.display());
+            let result = isolated(|| decide(&configuration, &path, backend.as_mut()));
+            match result {
+                Ok(decision) => decisions.push(decision),
+                Err(e) if options.fail_fast => return Err(e),
+                Err(e) => {
+                    println!("ERROR: Failed to handle file {}: {:?}", path.display(), e);
+                    summary.add(FileResult::Error);
+                }
+            }
+        }
+    }
+
+    // Nothing gets moved until everything has been looked at, so that copies of the same file
+    // can share a decision
+    let duplicates = dedup::share_decisions(&mut decisions);
+    phash::share_near_duplicates(&mut decisions, &earlier, settings.near_duplicate_distance);
+
+    let mut for_review: Vec<&Decision> = vec![];
+    for (index, decision) in decisions.iter().enumerate() {
+        if let Outcome::Decided { basis, .. } = decision.outcome
+            && basis.confidence() < options.min_confidence
+        {
+            for_review.push(decision);
+            summary.add(FileResult::LowConfidence);
+            continue;
+        }
+        let duplicate = move_duplicates && duplicates.contains(&index);
+        match isolated(|| apply_decision(decision, &options, duplicate)) {
+            Ok(result) => summary.add(result),
+            Err(e) if options.fail_fast => return Err(e),
+            Err(e) => {
+                match e.current_context() {
+                    MyError::Rotate { path } => println!(
+                        "ERROR: Renamed {} to {}, but failed to rotate it: {:?}",
+                        decision.filename.display(),
+                        path.display(),
+                        e
+                    ),
+                    _ => println!(
+                        "ERROR: Failed to move file {}: {:?}",
+                        decision.filename.display(),
+                        e
+                    ),
+                }
+                summary.add(FileResult::Error);
+            }
+        }
+    }
+
+    if !for_review.is_empty() {
+        println!(
+            "\nWARNING: Left {} file(s) alone because their confidence is below {}; check these by hand:",
+            for_review.len(),
+            options.min_confidence
+        );
+        for decision in for_review {
+            if let Outcome::Decided { ts, basis } = decision.outcome {
+                println!(
+                    "  {}: {} ({:?}, confidence {})",
+                    decision.filename.display(),
+                    prefix_for(ts),
+                    basis,
+                    basis.confidence()
+                );
+            }
+        }
+    }
+
+    println!("\n{summary}");
+    let code = summary.exit_code(&fail_on);
+    if code != 0 {
+        // Shut exiftool down properly first, since exit skips destructors
+        drop(backend);
+        std::process::exit(code);
+    }
+
+    // Remember ExifTool process closes when `exiftool` variable goes out of scope (Drop).
+    Ok(())
+}
+
+/// Paths from a --files-from list ("-" is stdin), one per line or, with -0, NUL separated like
+/// `find -print0` writes them
+fn read_file_list(list: &Path, null_separated: bool) -> io::Result<Vec<PathBuf>> {
+    let mut contents = vec![];
+    if list == Path::new("-") {
+        io::stdin().read_to_end(&mut contents)?;
+    } else {
+        contents = fs::read(list)?;
+    }
+
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    Ok(contents
+        .split(|byte| *byte == separator)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect())
+}
+
+/// An option's value, which has to be UTF-8 (unlike file names)
+fn utf8_arg(arg: Option<OsString>, problem: &str) -> String {
+    arg.and_then(|arg| arg.into_string().ok())
+        .unwrap_or_else(|| panic!("{problem}"))
+}
+
+/// Runs the work for one file, turning a panic into an error like any other, so that one bad
+/// file doesn't end the whole run
+fn isolated<T>(
+    work: impl FnOnce() -> error_stack::Result<T, MyError>,
+) -> error_stack::Result<T, MyError> {
+    panic::catch_unwind(AssertUnwindSafe(work)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "no message".to_string());
+        Err(report!(MyError::Panic).attach_printable(message))
+    })
+}
+
+/// The timestamp a file's prefix gives, if it has one, like 2019-07-04_15-33-37--
+fn prefix_time(path: &Path) -> Option<DateTime> {
+    let name = path.file_name()?.to_str()?;
+    if !already_prefixed(path) {
+        return None;
+    }
+    DateTime::strptime("%Y-%m-%d_%H-%M-%S", name.get(..19)?).ok()
+}
+
+/// Whether a file's name already starts with a prefix, like 2019-07-04_15-33-37--
+fn already_prefixed(path: &Path) -> bool {
+    static PREFIXED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"^[0-9]{4}-[0-9]{2}-[0-9]{2}_[0-9]{2}-[0-9]{2}-[0-9]{2}--").unwrap()
+    });
+    path.file_name()
+        .is_some_and(|name| PREFIXED.is_match(&name.to_string_lossy()))
+}
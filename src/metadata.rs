@@ -0,0 +1,182 @@
+//! Where file metadata comes from.  Everything downstream expects what `exiftool -j` gives with
+//! EXIFTOOL_ARGS; backends other than exiftool itself produce the same shape (see native.rs).
+
+use crate::{MyError, Settings, native};
+use error_stack::ResultExt;
+use exiftool::{ExifTool, ExifToolError};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// The backend names the metadata_backend setting accepts
+pub const BACKENDS: [&str; 3] = ["auto", "exiftool", "native"];
+
+pub trait MetadataBackend {
+    /// The name used for this backend in the metadata_backend setting
+    fn name(&self) -> &'static str;
+
+    /// Reads the metadata for a file, grouped like `exiftool -g0` output; None if this backend
+    /// doesn't understand this kind of file
+    fn read(&mut self, path: &Path) -> error_stack::Result<Option<Value>, MyError>;
+}
+
+/// Our always-on exiftool options.
+///
+/// The -d format string here causes all dates to be presented with time zones, even when the file
+/// does not, in fact, have any idea what the time zone for the given date is.  It uses the system
+/// default TZ in that case.  BUT.
+///
+/// The -api part says "act like the system default TZ is
+/// https://en.wikipedia.org/wiki/UTC−12:00 ".  😄
+///
+/// Which means that you can tell at a glance in the output whether a TZ was actually defined by
+/// something real in the metadata.  Like it's *technically* possible that someone took pictures
+/// in that time zone but not in a way that matters.
+///
+/// The ##DATE## part is just because AFAICT there's no way to get exiftool to *say* "this is a
+/// date tag", even though it itself clearly knows; this way "gather all date tags" is easy.
+///
+/// These go with every request to the -stay_open process, one argument per line, so the -d
+/// string's spaces need no quoting.
+pub const EXIFTOOL_ARGS: [&str; 6] = [
+    "-m",
+    "-g0",
+    "-api",
+    "TimeZone=GMT+12",
+    "-d",
+    "##DATE## %Y-%m-%d %H:%M:%S %z",
+];
+
+/// Runs exiftool, started on first use and kept running for the rest of the run
+pub struct ExifToolBackend {
+    executable: PathBuf,
+    exiftool: Option<ExifTool>,
+}
+
+impl ExifToolBackend {
+    pub fn new(executable: &Path) -> Self {
+        ExifToolBackend {
+            executable: executable.to_path_buf(),
+            exiftool: None,
+        }
+    }
+
+    /// The exiftool process, started if it isn't running
+    fn running(&mut self, path: &Path) -> error_stack::Result<&mut ExifTool, MyError> {
+        if self.exiftool.is_none() {
+            self.exiftool = Some(
+                ExifTool::with_executable(&self.executable)
+                    .change_context_lazy(|| MyError::ExifTool {
+                        path: path.to_path_buf(),
+                    })
+                    .attach_printable_lazy(|| {
+                        format!(
+                            "Couldn't run exiftool as {}; see the exiftool_path setting.",
+                            self.executable.display()
+                        )
+                    })?,
+            );
+        }
+        Ok(self.exiftool.as_mut().unwrap())
+    }
+}
+
+impl MetadataBackend for ExifToolBackend {
+    fn name(&self) -> &'static str {
+        "exiftool"
+    }
+
+    fn read(&mut self, path: &Path) -> error_stack::Result<Option<Value>, MyError> {
+        let result = match self.running(path)?.json(path, &EXIFTOOL_ARGS) {
+            // The process died (or was killed) since the last file; start another and try again
+            Err(
+                ExifToolError::ProcessTerminated
+                | ExifToolError::StderrDisconnected
+                | ExifToolError::Io(_),
+            ) => {
+                say!(
+                    "WARNING: exiftool stopped while reading {}; starting it again.",
+                    path.display()
+                );
+                self.exiftool = None;
+                self.running(path)?.json(path, &EXIFTOOL_ARGS)
+            }
+            result => result,
+        };
+        Ok(Some(result.change_context_lazy(|| MyError::ExifTool {
+            path: path.to_path_buf(),
+        })?))
+    }
+}
+
+/// Our own reader; no external programs needed
+pub struct NativeBackend;
+
+impl MetadataBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn read(&mut self, path: &Path) -> error_stack::Result<Option<Value>, MyError> {
+        native::read(path).change_context_lazy(|| MyError::ReadFile {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// The native reader for what it understands, exiftool for everything else, including files the
+/// native reader chokes on.  If exiftool can't be run, files the native reader doesn't understand
+/// are treated as not media.
+pub struct AutoBackend {
+    native: NativeBackend,
+    exiftool: ExifToolBackend,
+    exiftool_broken: bool,
+}
+
+impl MetadataBackend for AutoBackend {
+    fn name(&self) -> &'static str {
+        "auto"
+    }
+
+    fn read(&mut self, path: &Path) -> error_stack::Result<Option<Value>, MyError> {
+        match self.native.read(path) {
+            Ok(Some(metadata)) => return Ok(Some(metadata)),
+            Ok(None) if self.exiftool_broken => return Ok(None),
+            Ok(None) => {}
+            Err(e) if self.exiftool_broken => return Err(e),
+            Err(e) => say!(
+                "WARNING: Couldn't read {} natively ({:?}); trying exiftool.",
+                path.display(),
+                e
+            ),
+        }
+        match self.exiftool.read(path) {
+            Ok(metadata) => Ok(metadata),
+            Err(e) if self.exiftool.exiftool.is_none() => {
+                say!(
+                    "WARNING: exiftool isn't available ({:?}), so only files picnamion can read itself will be handled.",
+                    e
+                );
+                self.exiftool_broken = true;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub fn from_settings(settings: &Settings) -> Result<Box<dyn MetadataBackend>, String> {
+    match settings.metadata_backend.as_str() {
+        "auto" => Ok(Box::new(AutoBackend {
+            native: NativeBackend,
+            exiftool: ExifToolBackend::new(&settings.exiftool_path),
+            exiftool_broken: false,
+        })),
+        "exiftool" => Ok(Box::new(ExifToolBackend::new(&settings.exiftool_path))),
+        "native" => Ok(Box::new(NativeBackend)),
+        other => Err(format!(
+            "unknown metadata_backend {:?}; the choices are {}",
+            other,
+            BACKENDS.join(", ")
+        )),
+    }
+}
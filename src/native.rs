@@ -0,0 +1,589 @@
+//! A pure-Rust metadata reader for the common formats (JPEG, PNG, WebP, HEIC, MP4/MOV), so that
+//! picnamion doesn't need exiftool for them.
+//!
+//! It produces the same shape of JSON that `exiftool -j` gives with metadata::EXIFTOOL_ARGS: tags
+//! grouped by family 0 group name, with dates as "##DATE## %Y-%m-%d %H:%M:%S %z" and -1200
+//! meaning "no real time zone".  Only date tags the scorer knows about are produced, plus the
+//! OffsetTime tags and the MIME type.
+
+use jiff::Timestamp;
+use jiff::civil::DateTime;
+use jiff::tz::{self, Offset, TimeZone};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+/// What exiftool shows as the offset of a date with no time zone, given EXIFTOOL_ARGS's
+/// `-api TimeZone=GMT+12`
+const NO_ZONE: &str = "-1200";
+
+/// Seconds between the QuickTime epoch (1904-01-01) and the Unix one
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Don't read more than this much of a file looking for XMP, or of a video's moov box
+const MAX_READ: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Tiff,
+    /// HEIC/AVIF stills; EXIF lives in an item inside the ISO-BMFF structure
+    Heif,
+    /// MP4, MOV, 3GP and the like
+    Movie,
+}
+
+/// Works out the format and MIME type from the first few bytes of the file
+fn detect(header: &[u8]) -> Option<(Format, &'static str)> {
+    if header.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some((Format::Jpeg, "image/jpeg"))
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some((Format::Png, "image/png"))
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        Some((Format::Gif, "image/gif"))
+    } else if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        Some((Format::Webp, "image/webp"))
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        Some((Format::Tiff, "image/tiff"))
+    } else if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        match &header[8..12] {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+                Some((Format::Heif, "image/heic"))
+            }
+            b"avif" | b"avis" => Some((Format::Heif, "image/avif")),
+            b"qt  " => Some((Format::Movie, "video/quicktime")),
+            brand if brand.starts_with(b"3g") => Some((Format::Movie, "video/3gpp")),
+            b"isom" | b"iso2" | b"iso3" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42"
+            | b"avc1" | b"M4V " | b"M4VH" | b"M4VP" | b"mmp4" | b"MSNV" | b"XAVC" | b"dash" => {
+                Some((Format::Movie, "video/mp4"))
+            }
+            // Audio (M4A, M4B), Canon CR3, JPEG 2000 and whatever else uses ISO-BMFF; exiftool
+            // can say what they are
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Reads the metadata for a file; None if it's not a format we know how to read
+pub fn read(path: &Path) -> io::Result<Option<Value>> {
+    let mut file = fs::File::open(path)?;
+    let mut header = Vec::new();
+    file.by_ref().take(64).read_to_end(&mut header)?;
+    let Some((format, mimetype)) = detect(&header) else {
+        return Ok(None);
+    };
+
+    let mut groups = Groups::default();
+    groups.put("File", "MIMEType", mimetype.to_string());
+    file_times(&fs::metadata(path)?, &mut groups);
+
+    match format {
+        Format::Jpeg | Format::Png | Format::Webp | Format::Tiff | Format::Heif => {
+            exif(path, &mut groups);
+            xmp(path, &mut groups)?;
+            if format == Format::Png {
+                png_time(&mut file, &mut groups)?;
+            }
+        }
+        Format::Gif => {
+            xmp(path, &mut groups)?;
+        }
+        Format::Movie => {
+            movie(&mut file, &mut groups)?;
+        }
+    }
+
+    Ok(Some(groups.into_value()))
+}
+
+/// exiftool-style JSON being built up
+#[derive(Default)]
+struct Groups(Map<String, Value>);
+
+impl Groups {
+    fn put(&mut self, group: &str, tag: &str, value: String) {
+        let group = self
+            .0
+            .entry(group)
+            .or_insert_with(|| Value::Object(Map::new()));
+        // Like exiftool without -a, the first one wins
+        group
+            .as_object_mut()
+            .unwrap()
+            .entry(tag)
+            .or_insert(Value::String(value));
+    }
+
+    fn put_date(&mut self, group: &str, tag: &str, dt: DateTime, offset: Option<Offset>) {
+        self.put(group, tag, date_value(dt, offset));
+    }
+
+    fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+/// Formats a date the way EXIFTOOL_ARGS's -d option does
+fn date_value(dt: DateTime, offset: Option<Offset>) -> String {
+    let zone = match offset {
+        Some(offset) => {
+            let seconds = offset.seconds();
+            let sign = if seconds < 0 { '-' } else { '+' };
+            let minutes = seconds.abs() / 60;
+            format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+        }
+        None => NO_ZONE.to_string(),
+    };
+    format!("##DATE## {} {}", dt.strftime("%Y-%m-%d %H:%M:%S"), zone)
+}
+
+/// Parses "+02:00", "-0700", "+02" or "Z"
+pub fn parse_offset(s: &str) -> Option<Offset> {
+    static OFFSET_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^([+-])(\d\d)(?::?(\d\d))?$").unwrap());
+    if s == "Z" {
+        return Some(Offset::UTC);
+    }
+    let caps = OFFSET_RE.captures(s.trim())?;
+    let minutes = caps
+        .get(3)
+        .map_or(Ok(0), |m| m.as_str().parse::<i32>())
+        .ok()?;
+    let seconds = caps[2].parse::<i32>().ok()? * 3600 + minutes * 60;
+    let seconds = if &caps[1] == "-" { -seconds } else { seconds };
+    Offset::from_seconds(seconds).ok()
+}
+
+/// Parses EXIF's "YYYY:MM:DD HH:MM:SS"; None for blank or zeroed out values
+fn parse_exif_datetime(s: &str) -> Option<DateTime> {
+    let s = s.trim();
+    if s.len() < 19 {
+        return None;
+    }
+    // get, not indexing, since a corrupt value can have a multi-byte character anywhere
+    let date = s.get(..10)?.replace(':', "-");
+    format!("{}T{}", date, s.get(11..19)?)
+        .parse::<DateTime>()
+        .ok()
+}
+
+/// Parses ISO 8601 style dates as found in XMP and QuickTime metadata, like
+/// "2023-06-11T14:22:33.123-07:00".  Dates without a time aren't good enough.
+fn parse_iso_datetime(s: &str) -> Option<(DateTime, Option<Offset>)> {
+    static ISO_RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(
+            r"^(\d{4})[-:](\d\d)[-:](\d\d)[T ](\d\d):(\d\d)(?::(\d\d))?(?:\.\d+)?\s*(Z|[+-]\d\d:?\d\d)?$",
+        )
+        .unwrap()
+    });
+    let caps = ISO_RE.captures(s.trim())?;
+    let dt = format!(
+        "{}-{}-{}T{}:{}:{}",
+        &caps[1],
+        &caps[2],
+        &caps[3],
+        &caps[4],
+        &caps[5],
+        caps.get(6).map_or("00", |m| m.as_str())
+    )
+    .parse::<DateTime>()
+    .ok()?;
+    Some((dt, caps.get(7).and_then(|m| parse_offset(m.as_str()))))
+}
+
+/// exiftool shows file system times in its local time zone, which EXIFTOOL_ARGS sets to -12:00
+fn system_time_value(time: Timestamp) -> String {
+    time.to_zoned(TimeZone::fixed(tz::offset(-12)))
+        .strftime("##DATE## %Y-%m-%d %H:%M:%S %z")
+        .to_string()
+}
+
+fn file_times(metadata: &fs::Metadata, groups: &mut Groups) {
+    let from_system = |time: io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|time| Timestamp::try_from(time).ok())
+            .map(system_time_value)
+    };
+
+    if let Some(value) = from_system(metadata.modified()) {
+        groups.put("File", "FileModifyDate", value);
+    }
+    if let Some(value) = from_system(metadata.accessed()) {
+        groups.put("File", "FileAccessDate", value);
+    }
+    if let Ok(ctime) = Timestamp::new(metadata.ctime(), metadata.ctime_nsec() as i32) {
+        groups.put("File", "FileInodeChangeDate", system_time_value(ctime));
+    }
+}
+
+fn exif(path: &Path, groups: &mut Groups) {
+    use exif::{In, Tag};
+
+    let Ok(file) = fs::File::open(path) else {
+        return;
+    };
+    // Plenty of images just don't have any EXIF; that's fine
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut BufReader::new(file)) else {
+        return;
+    };
+
+    let ascii = |tag: Tag| -> Option<String> {
+        match &exif.get_field(tag, In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => values
+                .first()
+                .map(|v| String::from_utf8_lossy(v).trim().to_string())
+                .filter(|v| !v.is_empty()),
+            _ => None,
+        }
+    };
+
+    // (date tag, its sub-second tag, its offset tag, exiftool's name for it, exiftool's name for
+    // the composite that combines all three)
+    let dates = [
+        (
+            Tag::DateTimeOriginal,
+            Tag::SubSecTimeOriginal,
+            Tag::OffsetTimeOriginal,
+            "DateTimeOriginal",
+            "SubSecDateTimeOriginal",
+        ),
+        (
+            Tag::DateTimeDigitized,
+            Tag::SubSecTimeDigitized,
+            Tag::OffsetTimeDigitized,
+            "CreateDate",
+            "SubSecCreateDate",
+        ),
+        (
+            Tag::DateTime,
+            Tag::SubSecTime,
+            Tag::OffsetTime,
+            "ModifyDate",
+            "SubSecModifyDate",
+        ),
+    ];
+
+    for (date_tag, subsec_tag, offset_tag, name, composite) in dates {
+        let offset_str = ascii(offset_tag);
+        if let Some(offset_str) = &offset_str {
+            groups.put("EXIF", &offset_tag.to_string(), offset_str.clone());
+        }
+
+        let Some(dt) = ascii(date_tag).and_then(|s| parse_exif_datetime(&s)) else {
+            continue;
+        };
+        groups.put_date("EXIF", name, dt, None);
+
+        // exiftool only makes the composite when there's something to add to the plain date
+        if ascii(subsec_tag).is_some() || offset_str.is_some() {
+            let offset = offset_str.as_deref().and_then(parse_offset);
+            groups.put_date("Composite", composite, dt, offset);
+        }
+    }
+
+    // GPS times are always UTC
+    let gps_time = exif
+        .get_field(Tag::GPSTimeStamp, In::PRIMARY)
+        .and_then(|field| match &field.value {
+            exif::Value::Rational(parts) if parts.len() == 3 => {
+                Some(parts.iter().map(|part| part.to_f64()).collect::<Vec<_>>())
+            }
+            _ => None,
+        });
+    if let Some(date) = ascii(Tag::GPSDateStamp)
+        && let Some(time) = gps_time
+        && let Some(dt) = parse_exif_datetime(&format!(
+            "{} {:02}:{:02}:{:02}",
+            date, time[0] as u32, time[1] as u32, time[2] as u32
+        ))
+    {
+        groups.put_date("Composite", "GPSDateTime", dt, Some(Offset::UTC));
+    }
+}
+
+/// The XMP properties we care about, and exiftool's names for them
+const XMP_TAGS: [(&str, &str); 6] = [
+    ("xmp:CreateDate", "CreateDate"),
+    ("xmp:ModifyDate", "ModifyDate"),
+    ("xmp:MetadataDate", "MetadataDate"),
+    ("photoshop:DateCreated", "DateCreated"),
+    ("exif:GPSTimeStamp", "GPSDateTime"),
+    ("stEvt:when", "HistoryWhen"),
+];
+
+/// XMP is just XML embedded somewhere in the file, so rather than understand every container, look
+/// for the packet itself
+fn xmp(path: &Path, groups: &mut Groups) -> io::Result<()> {
+    static PROPERTY_RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"([A-Za-z]+:[A-Za-z]+)(?:\s*=\s*"([^"]*)"|>([^<]*)</)"#).unwrap()
+    });
+
+    let mut data = Vec::new();
+    fs::File::open(path)?
+        .take(MAX_READ)
+        .read_to_end(&mut data)?;
+    let text = String::from_utf8_lossy(&data);
+    let Some(start) = text.find("<x:xmpmeta") else {
+        return Ok(());
+    };
+    let end = text[start..]
+        .find("</x:xmpmeta>")
+        .map_or(text.len(), |end| start + end);
+    let packet = &text[start..end];
+
+    let mut history = vec![];
+    for caps in PROPERTY_RE.captures_iter(packet) {
+        let property = &caps[1];
+        let value = caps.get(2).or(caps.get(3)).unwrap().as_str();
+        let Some((_, name)) = XMP_TAGS.iter().find(|(p, _)| *p == property) else {
+            continue;
+        };
+        let Some((dt, offset)) = parse_iso_datetime(value) else {
+            continue;
+        };
+        if *name == "HistoryWhen" {
+            history.push((dt, offset));
+        } else {
+            groups.put_date("XMP", name, dt, offset);
+        }
+    }
+
+    // exiftool joins lists into one value, which only looks like a date if there's just one
+    if let [(dt, offset)] = history[..] {
+        groups.put_date("XMP", "HistoryWhen", dt, offset);
+    }
+
+    Ok(())
+}
+
+/// PNG's tIME chunk: the last modification time
+fn png_time(file: &mut fs::File, groups: &mut Groups) -> io::Result<()> {
+    file.seek(SeekFrom::Start(8))?;
+    let mut header = [0u8; 8];
+    while file.read_exact(&mut header).is_ok() {
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as i64;
+        match &header[4..8] {
+            b"tIME" if length == 7 => {
+                let mut data = [0u8; 7];
+                file.read_exact(&mut data)?;
+                let year = u16::from_be_bytes([data[0], data[1]]);
+                let datestr = format!(
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    year, data[2], data[3], data[4], data[5], data[6]
+                );
+                if let Ok(dt) = datestr.parse::<DateTime>() {
+                    groups.put_date("PNG", "ModifyDate", dt, None);
+                }
+                return Ok(());
+            }
+            b"IEND" => return Ok(()),
+            // Skip the data and the CRC
+            _ => {
+                file.seek(SeekFrom::Current(length + 4))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits ISO-BMFF data into its child boxes, as (type, body) pairs
+fn boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut found = vec![];
+    while data.len() >= 8 {
+        let mut size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as u64;
+        let kind: [u8; 4] = data[4..8].try_into().unwrap();
+        let mut header = 8;
+        if size == 1 {
+            if data.len() < 16 {
+                break;
+            }
+            size = u64::from_be_bytes(data[8..16].try_into().unwrap());
+            header = 16;
+        } else if size == 0 {
+            size = data.len() as u64;
+        }
+        if size < header as u64 || size > data.len() as u64 {
+            break;
+        }
+        found.push((kind, &data[header..size as usize]));
+        data = &data[size as usize..];
+    }
+    found
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, body)| body)
+}
+
+/// The creation and modification times from a mvhd, tkhd or mdhd box, which all start the same
+/// way; zero means unset
+fn header_times(body: &[u8]) -> (Option<DateTime>, Option<DateTime>) {
+    let (created, modified) = match body.first() {
+        Some(1) if body.len() >= 20 => (
+            u64::from_be_bytes(body[4..12].try_into().unwrap()),
+            u64::from_be_bytes(body[12..20].try_into().unwrap()),
+        ),
+        Some(0) if body.len() >= 12 => (
+            u32::from_be_bytes(body[4..8].try_into().unwrap()) as u64,
+            u32::from_be_bytes(body[8..12].try_into().unwrap()) as u64,
+        ),
+        _ => return (None, None),
+    };
+
+    // exiftool doesn't assume these are UTC (its QuickTimeUTC option is off by default), so it
+    // shows the UTC wall clock time as though it had no time zone; do the same
+    let convert = |seconds: u64| -> Option<DateTime> {
+        if seconds == 0 {
+            return None;
+        }
+        i64::try_from(seconds)
+            .ok()?
+            .checked_sub(QUICKTIME_EPOCH_OFFSET)
+            .and_then(|seconds| Timestamp::from_second(seconds).ok())
+            .map(|ts| ts.to_zoned(TimeZone::UTC).datetime())
+    };
+    (convert(created), convert(modified))
+}
+
+fn movie(file: &mut fs::File, groups: &mut Groups) -> io::Result<()> {
+    // Find the moov box at the top level, skipping over the (huge) media data
+    let len = file.metadata()?.len();
+    let mut pos = 0u64;
+    let mut moov = None;
+    while pos.saturating_add(8) <= len {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut header[8..16])?;
+            size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < header_len {
+            break;
+        }
+        if &header[4..8] == b"moov" {
+            let mut body = Vec::new();
+            file.by_ref()
+                .take((size - header_len).min(MAX_READ))
+                .read_to_end(&mut body)?;
+            moov = Some(body);
+            break;
+        }
+        // The size comes from the file, so it can be anything
+        let Some(next) = pos.checked_add(size) else {
+            break;
+        };
+        pos = next;
+    }
+    let Some(moov) = moov else {
+        return Ok(());
+    };
+
+    if let Some(mvhd) = child(&moov, b"mvhd") {
+        let (created, modified) = header_times(mvhd);
+        if let Some(dt) = created {
+            groups.put_date("QuickTime", "CreateDate", dt, None);
+        }
+        if let Some(dt) = modified {
+            groups.put_date("QuickTime", "ModifyDate", dt, None);
+        }
+    }
+
+    // Like exiftool without -a, only the first track counts
+    if let Some(trak) = child(&moov, b"trak") {
+        if let Some(tkhd) = child(trak, b"tkhd") {
+            let (created, modified) = header_times(tkhd);
+            if let Some(dt) = created {
+                groups.put_date("QuickTime", "TrackCreateDate", dt, None);
+            }
+            if let Some(dt) = modified {
+                groups.put_date("QuickTime", "TrackModifyDate", dt, None);
+            }
+        }
+        if let Some(mdhd) = child(trak, b"mdia").and_then(|mdia| child(mdia, b"mdhd")) {
+            let (created, modified) = header_times(mdhd);
+            if let Some(dt) = created {
+                groups.put_date("QuickTime", "MediaCreateDate", dt, None);
+            }
+            if let Some(dt) = modified {
+                groups.put_date("QuickTime", "MediaModifyDate", dt, None);
+            }
+        }
+    }
+
+    // Apple style metadata: moov/meta/keys names the items in moov/meta/ilst
+    if let Some(meta) = child(&moov, b"meta") {
+        let meta = meta_children(meta);
+        if let (Some(keys), Some(ilst)) = (child(meta, b"keys"), child(meta, b"ilst")) {
+            let names: Vec<&[u8]> = boxes(keys.get(8..).unwrap_or_default())
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect();
+            for (index, item) in boxes(ilst) {
+                let index = u32::from_be_bytes(index) as usize;
+                if index >= 1
+                    && names.get(index - 1) == Some(&&b"com.apple.quicktime.creationdate"[..])
+                    && let Some((dt, offset)) =
+                        ilst_string(item).and_then(|s| parse_iso_datetime(&s))
+                {
+                    groups.put_date("QuickTime", "CreationDate", dt, offset);
+                }
+            }
+        }
+    }
+
+    // moov/udta/©day, either QuickTime style or inside an iTunes style meta/ilst
+    if let Some(udta) = child(&moov, b"udta") {
+        let mut day = child(udta, b"\xa9day").and_then(|body| {
+            // A 16 bit length and a 16 bit language code, then the text
+            let len = u16::from_be_bytes(body.get(0..2)?.try_into().ok()?) as usize;
+            Some(String::from_utf8_lossy(body.get(4..4 + len)?).to_string())
+        });
+        if day.is_none()
+            && let Some(meta) = child(udta, b"meta")
+            && let Some(ilst) = child(meta_children(meta), b"ilst")
+        {
+            day = child(ilst, b"\xa9day").and_then(ilst_string);
+        }
+        if let Some((dt, offset)) = day.and_then(|s| parse_iso_datetime(&s)) {
+            groups.put_date("QuickTime", "ContentCreateDate", dt, offset);
+        }
+    }
+
+    Ok(())
+}
+
+/// 'meta' is a plain container in QuickTime files but has four bytes of version and flags first in
+/// MP4 files; either way it starts with a hdlr box
+fn meta_children(meta: &[u8]) -> &[u8] {
+    if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..).unwrap_or_default()
+    }
+}
+
+/// The text in an ilst item's 'data' box
+fn ilst_string(item: &[u8]) -> Option<String> {
+    let data = child(item, b"data")?;
+    // Four bytes of type, four of locale, then the value
+    Some(String::from_utf8_lossy(data.get(8..)?).to_string())
+}
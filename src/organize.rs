@@ -0,0 +1,146 @@
+//! Moving files into a date-based directory tree (like `Photos/2025/04/`) rather than just
+//! renaming them in place.
+
+use jiff::civil::DateTime;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The placeholders allowed in an `--organize` template, and the strftime format each one turns
+/// into
+const PLACEHOLDERS: [(&str, &str); 6] = [
+    ("{year}", "%Y"),
+    ("{month}", "%m"),
+    ("{day}", "%d"),
+    ("{hour}", "%H"),
+    ("{minute}", "%M"),
+    ("{second}", "%S"),
+];
+
+#[derive(Clone, Debug)]
+pub struct Organize {
+    /// Something like `{year}/{month}`; relative to `root`
+    template: String,
+    /// Where the date-based tree lives; an empty path means the current directory
+    root: PathBuf,
+    /// Leave the original filename alone instead of adding the prefix to it
+    keep_name: bool,
+}
+
+impl Organize {
+    pub fn new(template: &str, root: PathBuf, keep_name: bool) -> Result<Self, String> {
+        // Check for anything that looks like a placeholder that we don't know about, since
+        // otherwise a typo would silently produce a directory literally named "{yaer}"
+        let mut leftover = template.to_string();
+        for (placeholder, _) in PLACEHOLDERS {
+            leftover = leftover.replace(placeholder, "");
+        }
+        if leftover.contains('{') || leftover.contains('}') {
+            return Err(format!(
+                "template {} contains an unknown placeholder; the known ones are {}",
+                template,
+                PLACEHOLDERS.map(|(placeholder, _)| placeholder).join(", ")
+            ));
+        }
+
+        Ok(Organize {
+            template: template.to_string(),
+            root,
+            keep_name,
+        })
+    }
+
+    /// Where a file with the given decided timestamp should end up
+    pub fn dest_path(&self, ts: DateTime, filepath: &Path, prefix: &str) -> PathBuf {
+        let mut subdir = self.template.clone();
+        for (placeholder, format) in PLACEHOLDERS {
+            subdir = subdir.replace(placeholder, &ts.strftime(format).to_string());
+        }
+
+        let mut newpath = self.root.join(subdir);
+        if self.keep_name {
+            newpath.push(filepath.file_name().unwrap_or_default());
+        } else {
+            newpath.push(prefixed_name(filepath, prefix));
+        }
+        newpath
+    }
+}
+
+/// The file's name with the prefix added; it doesn't have to be UTF-8
+pub fn prefixed_name(filepath: &Path, prefix: &str) -> OsString {
+    let mut name = OsString::from(prefix);
+    name.push(filepath.file_name().unwrap_or_default());
+    name
+}
+
+/// Moves a file, creating the destination directory as needed.  If the destination is on a
+/// different filesystem, the file is copied, the copy is checked against the original, and only
+/// then is the original removed.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_verify_delete(from, to),
+        other => other,
+    }
+}
+
+fn copy_verify_delete(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to)?;
+
+    // The file's modification time is one of our sources of timestamps, so keep it
+    let modified = fs::metadata(from)?.modified()?;
+    fs::File::options()
+        .write(true)
+        .open(to)?
+        .set_modified(modified)?;
+
+    if !same_contents(from, to)? {
+        // Don't leave a bad copy lying around
+        fs::remove_file(to)?;
+        return Err(io::Error::other(format!(
+            "copy of {} to {} does not match the original",
+            from.display(),
+            to.display()
+        )));
+    }
+
+    fs::remove_file(from)
+}
+
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a_file = fs::File::open(a)?;
+    let mut b_file = fs::File::open(b)?;
+    if a_file.metadata()?.len() != b_file.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut a_buf = vec![0u8; 64 * 1024];
+    let mut b_buf = vec![0u8; 64 * 1024];
+    loop {
+        let a_len = read_full(&mut a_file, &mut a_buf)?;
+        let b_len = read_full(&mut b_file, &mut b_buf)?;
+        if a_buf[..a_len] != b_buf[..b_len] {
+            return Ok(false);
+        }
+        if a_len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Like read_exact, but a short read at the end of the file is fine
+fn read_full(file: &mut fs::File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match file.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
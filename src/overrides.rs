@@ -0,0 +1,293 @@
+//! Timestamps we simply know, for files that can't be dated any other way (scanned prints, say),
+//! and time zones for files whose times were recorded somewhere other than home.  These are the
+//! "overrides" timestamp source (see sources.rs), and an override timestamp beats every other
+//! source.
+//!
+//! Overrides come from the file named by the overrides_file setting and from
+//! .picnamion-overrides.json and .picnamion-overrides.csv files in a file's directory and its
+//! parents.  Each entry says which files it's for, with "path:", "glob:" or "hash:" (the SHA-256
+//! of the file's contents), and either the timestamp to use or the time zone to take the file's
+//! times as being in.  In JSON:
+//!
+//!   [{ "match": "glob:scans/1998/*", "timestamp": "1998-06-12" },
+//!    { "match": "path:trip/IMG_0001.jpg", "time_zone": "Europe/Berlin" }]
+//!
+//! and in CSV, with an optional header line:
+//!
+//!   match,timestamp,time_zone
+//!   glob:scans/1998/*,1998-06-12,
+//!   path:trip/IMG_0001.jpg,,Europe/Berlin
+//!
+//! Relative paths and globs are relative to the directory the overrides file is in.  The first
+//! entry that matches a file is the one used, looking in the innermost directory's files first
+//! and overrides_file last.
+
+use globset::{GlobBuilder, GlobMatcher};
+use jiff::civil::{Date, DateTime};
+use jiff::{Timestamp, Zoned, tz::TimeZone};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Settings;
+
+/// The names of the per-directory overrides files
+pub const DIRECTORY_OVERRIDES: [&str; 2] =
+    [".picnamion-overrides.json", ".picnamion-overrides.csv"];
+
+#[derive(Clone, Debug)]
+pub struct Override {
+    matcher: Matcher,
+    pub action: Action,
+    /// Which file and entry it came from, for messages and traces
+    pub origin: String,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    /// An absolute path
+    Path(PathBuf),
+    /// Matched against absolute paths; * doesn't match /
+    Glob(GlobMatcher),
+    Hash(String),
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Matcher::Path(path) => write!(f, "path:{}", path.display()),
+            Matcher::Glob(glob) => write!(f, "glob:{}", glob.glob()),
+            Matcher::Hash(hash) => write!(f, "hash:{hash}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Use this timestamp
+    Timestamp(OverrideTime),
+    /// Use this time zone as home_time_zone
+    TimeZone(String),
+}
+
+/// A timestamp as given in an overrides file
+#[derive(Clone, Debug)]
+pub enum OverrideTime {
+    /// Just a date; the time is the date_only_time setting
+    Date(Date),
+    /// A wall clock time, used as is
+    Civil(DateTime),
+    /// A time with an IANA time zone, like 1998-06-12T14:30:00[Europe/Berlin]; used as the wall
+    /// clock time there
+    Zoned(Zoned),
+    /// A time with just an offset, like 1998-06-12T14:30:00-07:00; shown in home_time_zone
+    Instant(Timestamp),
+}
+
+impl OverrideTime {
+    fn parse(value: &str) -> Result<OverrideTime, String> {
+        if let Ok(date) = value.parse::<Date>()
+            && !value.contains('T')
+        {
+            Ok(OverrideTime::Date(date))
+        } else if let Ok(zoned) = value.parse::<Zoned>() {
+            Ok(OverrideTime::Zoned(zoned))
+        } else if let Ok(timestamp) = value.parse::<Timestamp>() {
+            Ok(OverrideTime::Instant(timestamp))
+        } else if let Ok(datetime) = value.parse::<DateTime>() {
+            Ok(OverrideTime::Civil(datetime))
+        } else {
+            Err(format!(
+                "bad timestamp {value:?}; it should be like 1998-06-12, 1998-06-12T14:30:00, 1998-06-12T14:30:00-07:00 or 1998-06-12T14:30:00[Europe/Berlin]"
+            ))
+        }
+    }
+
+    /// The wall clock time to use in the prefix
+    pub fn datetime(&self, settings: &Settings, home_tz: &TimeZone) -> DateTime {
+        match self {
+            OverrideTime::Date(date) => date.to_datetime(settings.date_only_time),
+            OverrideTime::Civil(datetime) => *datetime,
+            OverrideTime::Zoned(zoned) => zoned.datetime(),
+            OverrideTime::Instant(timestamp) => timestamp.to_zoned(home_tz.clone()).datetime(),
+        }
+    }
+}
+
+impl fmt::Display for OverrideTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverrideTime::Date(date) => write!(f, "{date}"),
+            OverrideTime::Civil(datetime) => write!(f, "{datetime}"),
+            OverrideTime::Zoned(zoned) => write!(f, "{zoned}"),
+            OverrideTime::Instant(timestamp) => write!(f, "{timestamp}"),
+        }
+    }
+}
+
+impl fmt::Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.action {
+            Action::Timestamp(time) => {
+                write!(f, "{} ({}) says {}", self.matcher, self.origin, time)
+            }
+            Action::TimeZone(name) => {
+                write!(
+                    f,
+                    "{} ({}) says time zone {}",
+                    self.matcher, self.origin, name
+                )
+            }
+        }
+    }
+}
+
+/// One entry, as written in the file
+#[derive(serde::Deserialize)]
+struct RawOverride {
+    #[serde(rename = "match")]
+    matcher: String,
+    #[serde(default)]
+    timestamp: Option<String>,
+    #[serde(default)]
+    time_zone: Option<String>,
+}
+
+impl RawOverride {
+    fn parse(self, base: &Path) -> Result<(Matcher, Action), String> {
+        let matcher = if let Some(path) = self.matcher.strip_prefix("path:") {
+            Matcher::Path(absolute(&base.join(path))?)
+        } else if let Some(glob) = self.matcher.strip_prefix("glob:") {
+            let glob = absolute(&base.join(glob))?;
+            Matcher::Glob(
+                GlobBuilder::new(&glob.to_string_lossy())
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| format!("bad glob {:?}: {e}", self.matcher))?
+                    .compile_matcher(),
+            )
+        } else if let Some(hash) = self.matcher.strip_prefix("hash:") {
+            Matcher::Hash(hash.to_ascii_lowercase())
+        } else {
+            return Err(format!(
+                "{:?} should start with path:, glob: or hash:",
+                self.matcher
+            ));
+        };
+
+        let action = match (self.timestamp, self.time_zone) {
+            (Some(timestamp), None) => Action::Timestamp(OverrideTime::parse(&timestamp)?),
+            (None, Some(name)) => {
+                TimeZone::get(&name).map_err(|e| format!("bad time zone {name:?}: {e}"))?;
+                Action::TimeZone(name)
+            }
+            _ => {
+                return Err(format!(
+                    "{:?} needs either a timestamp or a time_zone, but not both",
+                    self.matcher
+                ));
+            }
+        };
+
+        Ok((matcher, action))
+    }
+}
+
+fn absolute(path: &Path) -> Result<PathBuf, String> {
+    std::path::absolute(path).map_err(|e| format!("bad path {}: {e}", path.display()))
+}
+
+/// Reads one overrides file, CSV if its name ends in .csv and JSON otherwise
+fn load(path: &Path) -> Result<Vec<Override>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("could not read overrides file {}: {e}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let entries: Vec<(String, RawOverride)> = if path.extension().is_some_and(|ext| ext == "csv") {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .filter(|(_, line)| line.trim() != "match,timestamp,time_zone")
+            .map(|(number, line)| {
+                // Split from the right, so that a path or glob can have commas in it
+                let mut fields = line.rsplitn(3, ',');
+                let (Some(time_zone), Some(timestamp), Some(matcher)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(format!(
+                        "{} line {}: expected match,timestamp,time_zone",
+                        path.display(),
+                        number + 1
+                    ));
+                };
+                let field = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+                Ok((
+                    format!("{} line {}", path.display(), number + 1),
+                    RawOverride {
+                        matcher: matcher.trim().to_string(),
+                        timestamp: field(timestamp),
+                        time_zone: field(time_zone),
+                    },
+                ))
+            })
+            .collect::<Result<_, String>>()?
+    } else {
+        serde_json::from_str::<Vec<RawOverride>>(&contents)
+            .map_err(|e| format!("bad overrides file {}: {e}", path.display()))?
+            .into_iter()
+            .enumerate()
+            .map(|(index, raw)| (format!("{} entry {}", path.display(), index + 1), raw))
+            .collect()
+    };
+
+    entries
+        .into_iter()
+        .map(|(origin, raw)| {
+            let (matcher, action) = raw.parse(base).map_err(|e| format!("{origin}: {e}"))?;
+            Ok(Override {
+                matcher,
+                action,
+                origin,
+            })
+        })
+        .collect()
+}
+
+/// Every override that could apply to files in `dir`, in the order they're tried
+pub fn for_directory(dir: &Path, settings: &Settings) -> Result<Vec<Override>, String> {
+    let mut files: Vec<PathBuf> = dir
+        .ancestors()
+        .flat_map(|ancestor| DIRECTORY_OVERRIDES.map(|name| ancestor.join(name)))
+        .filter(|path| path.is_file())
+        .collect();
+    files.extend(settings.overrides_file.clone());
+
+    let mut overrides = vec![];
+    for file in files {
+        overrides.extend(load(&file)?);
+    }
+    Ok(overrides)
+}
+
+/// The override for a file, if there is one.  `hash` gives the file's SHA-256 as dedup::hash_file
+/// does; it's only called when there's a hash: override to check the file against, since hashing a
+/// big file that a path or glob already matched would be a waste.
+pub fn find<'a, 'h, E>(
+    overrides: &'a [Override],
+    path: &Path,
+    hash: impl Fn() -> Result<&'h str, E>,
+) -> Result<Option<&'a Override>, E> {
+    let path = std::path::absolute(path).unwrap_or(path.to_path_buf());
+    for entry in overrides {
+        let matched = match &entry.matcher {
+            Matcher::Path(wanted) => *wanted == path,
+            Matcher::Glob(glob) => glob.is_match(&path),
+            Matcher::Hash(wanted) => wanted == hash()?,
+        };
+        if matched {
+            return Ok(Some(entry));
+        }
+    }
+    Ok(None)
+}
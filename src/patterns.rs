@@ -0,0 +1,173 @@
+//! Built-in filename patterns for well known sources of pictures, so that nobody has to write (and
+//! debug) their own regex for WhatsApp.  A configuration uses one by putting "builtin:NAME" in
+//! file_regexes or extra_file_regexes.  Every pattern comes with example filenames and what they
+//! should give, which check-config tests.
+//!
+//! Bump VERSION whenever a pattern changes what it matches, since that can change what existing
+//! configurations do.
+
+/// The version of the pattern library as a whole
+pub const VERSION: u32 = 1;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub regex: &'static str,
+    /// (filename, timestamp) pairs; timestamps with an offset are compared as instants, others as
+    /// wall clock times
+    pub examples: &'static [(&'static str, &'static str)],
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "pixel",
+        description: "Pixel camera app, all dates/times in UTC",
+        regex: r"PXL_(?P<year>[0-9][0-9][0-9][0-9])(?P<month>[0-9][0-9])(?P<day>[0-9][0-9])_(?P<hour>[0-9][0-9])(?P<minute>[0-9][0-9])(?P<second>[0-9][0-9])(?P<subsec>[0-9][0-9][0-9])[.]",
+        examples: &[
+            ("PXL_20250811_185849536.MP.jpg", "2025-08-11T18:58:49.536Z"),
+            ("PXL_20250901_172238060.mp4", "2025-09-01T17:22:38.06Z"),
+        ],
+    },
+    Builtin {
+        name: "android_camera",
+        description: "Android cameras and screenshots, and similar",
+        regex: r"(video|Screenshot|MOV|VID|IMG|image|screen)[-_ ](?P<year>[0-9][0-9][0-9][0-9])[-_]?(?P<month>[0-9][0-9])[-_]?(?P<day>[0-9][0-9])[ _-](?P<hour>[0-9][0-9])[-_.]?(?P<minute>[0-9][0-9])[-_.]?(?P<second>[0-9][0-9])[0-9]?[.][a-zA-Z0-9_.]+$",
+        examples: &[
+            ("VID_20120818_211233.mp4", "2012-08-18T21:12:33"),
+            ("IMG_20120802_200043.jpg", "2012-08-02T20:00:43"),
+            ("Screenshot_20250730-075454.png", "2025-07-30T07:54:54"),
+        ],
+    },
+    Builtin {
+        name: "samsung",
+        description: "Samsung camera app",
+        regex: r"(^|/)(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})(\([0-9]+\))?[.][a-zA-Z0-9]+$",
+        examples: &[
+            ("20190704_153337.jpg", "2019-07-04T15:33:37"),
+            ("DCIM/Camera/20190704_153337(0).mp4", "2019-07-04T15:33:37"),
+        ],
+    },
+    Builtin {
+        name: "iphone_export",
+        description: "iPhone pictures as named by camera uploads and exports, like Dropbox's",
+        regex: r"(^|/)(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2}) (?P<hour>[0-9]{2})[.](?P<minute>[0-9]{2})[.](?P<second>[0-9]{2})(-[0-9]+)?[.][a-zA-Z0-9]+$",
+        examples: &[
+            ("2019-07-04 15.33.37.heic", "2019-07-04T15:33:37"),
+            (
+                "Camera Uploads/2019-07-04 15.33.37-1.jpg",
+                "2019-07-04T15:33:37",
+            ),
+        ],
+    },
+    Builtin {
+        name: "macos_screenshot",
+        description: "macOS screenshots and screen recordings, with 24 or 12 hour times",
+        regex: r"Screen ?(Shot|shot|Recording) (?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2}) at (?P<hour>[0-9]{1,2})[.](?P<minute>[0-9]{2})[.](?P<second>[0-9]{2})( ?(?P<ampm>[AP]M))?",
+        examples: &[
+            (
+                "Screenshot 2023-05-01 at 14.02.03.png",
+                "2023-05-01T14:02:03",
+            ),
+            (
+                "Screen Shot 2019-07-04 at 3.33.37 PM.png",
+                "2019-07-04T15:33:37",
+            ),
+            (
+                "Screen Recording 2019-07-04 at 12.01.02 AM.mov",
+                "2019-07-04T00:01:02",
+            ),
+        ],
+    },
+    Builtin {
+        name: "playstation",
+        description: "PlayStation 5 screenshots and clips",
+        regex: r"_(?P<year>[0-9][0-9][0-9][0-9])(?P<month>[0-9][0-9])(?P<day>[0-9][0-9])(?P<hour>[0-9][0-9])(?P<minute>[0-9][0-9])(?P<second>[0-9][0-9])[.][a-zA-Z0-9_.]+$",
+        examples: &[(
+            "PS5/Screenshots/Horizon Forbidden West/Horizon Forbidden West_20220304232349.jpg",
+            "2022-03-04T23:23:49",
+        )],
+    },
+    Builtin {
+        name: "telegram_save_as",
+        description: "Telegram's \"Save As\"",
+        regex: r"photo_(?P<year>[0-9][0-9][0-9][0-9])-(?P<month>[0-9][0-9])-(?P<day>[0-9][0-9])_(?P<hour>[0-9][0-9])-(?P<minute>[0-9][0-9])-(?P<second>[0-9][0-9])[.][a-zA-Z0-9_.]+$",
+        examples: &[("photo_2023-01-01_07-52-05.jpg", "2023-01-01T07:52:05")],
+    },
+    Builtin {
+        name: "telegram_export",
+        description: "Telegram's \"Export Chat\"",
+        regex: r"(photo|video)_[0-9]+[@](?P<day>[0-9][0-9])-(?P<month>[0-9][0-9])-(?P<year>[0-9][0-9][0-9][0-9])_(?P<hour>[0-9][0-9])-(?P<minute>[0-9][0-9])-(?P<second>[0-9][0-9])[.][a-zA-Z0-9_.]+$",
+        examples: &[("photo_103@21-11-2021_14-08-55.jpg", "2021-11-21T14:08:55")],
+    },
+    Builtin {
+        name: "whatsapp",
+        description: "WhatsApp's desktop and iPhone saves",
+        regex: r"WhatsApp[ ](Image|Video)[ ](?P<year>[0-9][0-9][0-9][0-9])-(?P<month>[0-9][0-9])-(?P<day>[0-9][0-9])[ ]at[ ](?P<hour>[0-9][0-9]).(?P<minute>[0-9][0-9]).(?P<second>[0-9][0-9])[._]",
+        examples: &[
+            (
+                "WhatsApp Image 2022-12-26 at 05.38.32.jpeg",
+                "2022-12-26T05:38:32",
+            ),
+            (
+                "WhatsApp Video 2024-08-26 at 17.52.24_ca2620f8.mp4",
+                "2024-08-26T17:52:24",
+            ),
+        ],
+    },
+    Builtin {
+        name: "whatsapp_android",
+        description: "WhatsApp on Android, which only has the date",
+        regex: r"(IMG|VID|AUD|PTT)-(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})-WA[0-9]+(?P<date_only>)",
+        examples: &[
+            ("IMG-20190704-WA0001.jpg", "2019-07-04"),
+            ("VID-20230101-WA0012.mp4", "2023-01-01"),
+        ],
+    },
+    Builtin {
+        name: "signal",
+        description: "Signal's saved attachments",
+        regex: r"signal-(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})-(?P<hour>[0-9]{2})-?(?P<minute>[0-9]{2})-?(?P<second>[0-9]{2})(-(?P<subsec>[0-9]{3}))?",
+        examples: &[
+            ("signal-2019-07-04-153337.jpg", "2019-07-04T15:33:37"),
+            (
+                "signal-2023-07-04-15-33-37-123.jpg",
+                "2023-07-04T15:33:37.123",
+            ),
+            ("signal-2023-07-04-153337_002.jpeg", "2023-07-04T15:33:37"),
+        ],
+    },
+    Builtin {
+        name: "wyze_clip",
+        description: "Wyze camera clips, named with milliseconds since the epoch",
+        regex: r"(^|/)(?P<sse>[0-9]{10})[0-9]{3}_.{24}.mp4$",
+        examples: &[(
+            "1744189190638_80482C470F8E131744189190.mp4",
+            "2025-04-09T08:59:50Z",
+        )],
+    },
+    Builtin {
+        name: "wyze_photo",
+        description: "Wyze camera photos, with milliseconds since the epoch after the camera ID",
+        regex: r"(^|/)[0-9A-Z]{12}_(?P<sse>[0-9]{10})[0-9]{3}.jpg$",
+        examples: &[("80482C38EA21_1745805915377.jpg", "2025-04-28T02:05:15Z")],
+    },
+    Builtin {
+        name: "wyze_epoch",
+        description: "Wyze camera downloads named with just (milli)seconds since the epoch",
+        regex: r"(^|/)(?P<sse>[0-9]{10})([0-9]{3})?.(mp4|png|jpg)$",
+        examples: &[("1745810247.mp4", "2025-04-28T03:17:27Z")],
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// All the names, for error messages
+pub fn names() -> String {
+    BUILTINS
+        .iter()
+        .map(|builtin| builtin.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,144 @@
+//! Perceptual hashing, for spotting images that are the same picture even though the bytes
+//! differ, which is what happens when a messaging app recompresses a photo (and, usually, strips
+//! its metadata while it's at it).
+
+use image::imageops::{self, FilterType};
+use jiff::civil::DateTime;
+use std::path::{Path, PathBuf};
+
+use crate::trace::Step;
+use crate::{Basis, Decision, Outcome};
+
+/// A 64 bit difference hash ("dHash"): shrink the image to 9x8 grey pixels and record whether each
+/// pixel is brighter than the one to its right.  Returns None for anything the pure-Rust decoders
+/// can't read (HEIC, for example).
+pub fn dhash(path: &Path) -> Option<u64> {
+    let image = image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+
+    // Cheaply get it down to something small first; resizing a full size photo straight to 9x8
+    // with a real filter is slow
+    let small = image.thumbnail(64, 64).to_luma8();
+    let tiny = imageops::resize(&small, 9, 8, FilterType::Triangle);
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if tiny.get_pixel(x, y)[0] > tiny.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Some(hash)
+}
+
+/// Number of differing bits between two hashes; 0 is identical, 64 is as different as possible
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Images we couldn't date from their own metadata or filename: either we couldn't decide at all,
+/// or all we had was a date on its own or the file's modification time.
+fn is_undated(decision: &Decision) -> bool {
+    decision.mimetype.contains("image")
+        && matches!(
+            decision.outcome,
+            Outcome::Undecided { .. }
+                | Outcome::Decided {
+                    basis: Basis::DateOnly | Basis::FileTime,
+                    ..
+                }
+        )
+}
+
+/// Gives undated images the timestamp of the closest properly dated image, as long as their
+/// perceptual hashes are no more than `max_distance` bits apart.  The dated images are the ones
+/// in the run and `earlier`: files renamed by earlier runs, with the timestamps their prefixes
+/// give.
+pub fn share_near_duplicates(
+    decisions: &mut [Decision],
+    earlier: &[(PathBuf, DateTime)],
+    max_distance: u32,
+) {
+    let undated: Vec<usize> = (0..decisions.len())
+        .filter(|&index| is_undated(&decisions[index]))
+        .collect();
+    if undated.is_empty() {
+        return;
+    }
+
+    let mut donors: Vec<(PathBuf, DateTime)> = decisions
+        .iter()
+        .filter_map(|decision| match decision.outcome {
+            Outcome::Decided { ts, basis }
+                if decision.mimetype.contains("image")
+                    && basis < Basis::DateOnly
+                    && !matches!(basis, Basis::NearDuplicate { .. }) =>
+            {
+                Some((decision.filename.clone(), ts))
+            }
+            _ => None,
+        })
+        .collect();
+    donors.extend_from_slice(earlier);
+    if donors.is_empty() {
+        return;
+    }
+
+    // Decoding images is slow, so only hash the donors we actually need, and each only once
+    let mut donor_hashes: Vec<Option<Option<u64>>> = vec![None; donors.len()];
+
+    let mut printed_header = false;
+    for index in undated {
+        let Some(hash) = dhash(&decisions[index].filename) else {
+            continue;
+        };
+
+        let mut best: Option<(u32, usize)> = None;
+        for (donor, (path, _)) in donors.iter().enumerate() {
+            // A file renamed earlier and given again isn't a copy of itself
+            if *path == decisions[index].filename {
+                continue;
+            }
+            if let Some(donor_hash) = *donor_hashes[donor].get_or_insert_with(|| dhash(path)) {
+                let dist = distance(hash, donor_hash);
+                if dist <= max_distance && best.is_none_or(|(best_dist, _)| dist < best_dist) {
+                    best = Some((dist, donor));
+                }
+            }
+        }
+
+        if let Some((dist, donor)) = best {
+            let (donor_path, ts) = &donors[donor];
+            if !printed_header {
+                say!("\n\n********************** near duplicates\n");
+                printed_header = true;
+            }
+            say!(
+                "INFO: {} looks like a re-encoded copy of {} (perceptual hash distance {} of 64 bits); using its timestamp, prefix {}",
+                decisions[index].filename.display(),
+                donor_path.display(),
+                dist,
+                crate::prefix_for(*ts)
+            );
+            let step = Step::new(
+                "near_duplicate",
+                format!(
+                    "Looks like a re-encoded copy of {} (perceptual hash distance {} of 64 bits), so using its timestamp",
+                    donor_path.display(),
+                    dist
+                ),
+            );
+            decisions[index].trace.push(step);
+            decisions[index].outcome = Outcome::Decided {
+                ts: *ts,
+                basis: Basis::NearDuplicate { distance: dist },
+            };
+        }
+    }
+}
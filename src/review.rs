@@ -0,0 +1,278 @@
+//! Somewhere to put files that need a human to pick their timestamp, out of the way of the ones
+//! that were renamed.  Each file moved there gets a NAME.picnamion.json sidecar listing the
+//! timestamps it could have, which `picnamion resolve` reads back.
+
+use crate::organize::prefixed_name;
+use crate::summary::FileResult;
+use crate::{Decision, MyError, Outcome, do_move_or_report, prefix_for};
+use error_stack::ResultExt;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Added to a file's name for its sidecar
+const SIDECAR_SUFFIX: &str = ".picnamion.json";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Sidecar {
+    /// Where the file was before it was moved for review
+    original: SidecarPath,
+    /// Why it needs reviewing
+    reason: String,
+    choices: Vec<SidecarChoice>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SidecarChoice {
+    timestamp: String,
+    /// Where the timestamp came from
+    from: String,
+    prefix: String,
+    /// The command that puts the file back with this prefix
+    mv: String,
+}
+
+/// A path as it goes in a sidecar: text when it's UTF-8, so people can read it, and otherwise its
+/// bytes, so that putting the file back doesn't mangle the name of a directory on the way
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum SidecarPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl SidecarPath {
+    fn new(path: &Path) -> SidecarPath {
+        match path.to_str() {
+            Some(text) => SidecarPath::Text(text.to_string()),
+            None => SidecarPath::Bytes(path.as_os_str().as_bytes().to_vec()),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            SidecarPath::Text(text) => Path::new(text),
+            SidecarPath::Bytes(bytes) => Path::new(OsStr::from_bytes(bytes)),
+        }
+    }
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(SIDECAR_SUFFIX);
+    name.into()
+}
+
+/// Where a file under review goes back to with `prefix`: its original directory, with the prefix
+/// added to its name
+fn destination(path: &Path, original: &Path, prefix: &str) -> PathBuf {
+    original.with_file_name(prefixed_name(path, prefix))
+}
+
+/// Moves a file into `review_dir` and writes its sidecar, or just says it would without
+/// `do_move`.  Undecided files offer all their candidate timestamps; decided ones (which were
+/// not confident enough) offer the one that was decided on.
+pub fn quarantine(
+    decision: &Decision,
+    review_dir: &Path,
+    reason: &str,
+    do_move: bool,
+) -> error_stack::Result<FileResult, MyError> {
+    let filepath = decision.filename.as_path();
+    let newpath = review_dir.join(filepath.file_name().expect("a file has a name"));
+
+    if let Some(existing) = [newpath.clone(), sidecar_path(&newpath)]
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        println!(
+            "ERROR: Not moving file {} for review because {} already exists.",
+            filepath.display(),
+            existing.display()
+        );
+        return Ok(FileResult::Collision);
+    }
+
+    let choices = match &decision.outcome {
+        Outcome::Undecided { choices } => choices
+            .iter()
+            .map(|choice| (choice.ts, choice.from.clone()))
+            .collect(),
+        Outcome::Decided { ts, basis } => {
+            vec![(
+                *ts,
+                format!("{:?}, confidence {}", basis, basis.confidence()),
+            )]
+        }
+        Outcome::NotMedia => vec![],
+    };
+    let choices = choices
+        .into_iter()
+        .map(|(ts, from)| {
+            let prefix = prefix_for(ts);
+            let back = destination(&newpath, filepath, &prefix);
+            SidecarChoice {
+                timestamp: ts.to_string(),
+                from,
+                mv: format!("mv '{}' '{}'", newpath.display(), back.display()),
+                prefix,
+            }
+        })
+        .collect();
+    let sidecar = Sidecar {
+        original: SidecarPath::new(filepath),
+        reason: reason.to_string(),
+        choices,
+    };
+
+    if !do_move {
+        do_move_or_report(filepath, &newpath, do_move, true)?;
+        return Ok(match decision.outcome {
+            Outcome::Undecided { .. } => FileResult::Ambiguous,
+            _ => FileResult::LowConfidence,
+        });
+    }
+
+    // The sidecar goes first, so that a file is never under review without a record of where it
+    // came from
+    let sidecar_file = sidecar_path(&newpath);
+    fs::create_dir_all(review_dir)
+        .and_then(|()| {
+            fs::write(
+                &sidecar_file,
+                serde_json::to_string_pretty(&sidecar).expect("a sidecar is always valid JSON")
+                    + "\n",
+            )
+        })
+        .change_context_lazy(|| MyError::Sidecar {
+            path: sidecar_file.clone(),
+        })?;
+
+    if let Err(e) = do_move_or_report(filepath, &newpath, do_move, true) {
+        if let Err(remove_error) = fs::remove_file(&sidecar_file) {
+            println!(
+                "WARNING: Could not remove sidecar {} for a file that was not moved: {}",
+                sidecar_file.display(),
+                remove_error
+            );
+        }
+        return Err(e);
+    }
+
+    Ok(FileResult::Quarantined)
+}
+
+fn read_sidecar(path: &Path) -> error_stack::Result<Sidecar, MyError> {
+    let sidecar_file = sidecar_path(path);
+    let contents = fs::read(&sidecar_file).change_context_lazy(|| MyError::Sidecar {
+        path: sidecar_file.clone(),
+    })?;
+    serde_json::from_slice(&contents).change_context_lazy(|| MyError::Sidecar {
+        path: sidecar_file.clone(),
+    })
+}
+
+/// `picnamion resolve [-m] [--pick N] FILES_OR_DIRS...`: lists the choices for files under review
+/// (every file with a sidecar, for a directory), or with --pick moves each of FILES back where it
+/// came from with its Nth choice of prefix
+pub fn run(mut args: impl Iterator<Item = OsString>) -> error_stack::Result<(), MyError> {
+    let mut do_move = false;
+    let mut pick: Option<usize> = None;
+    let mut paths: Vec<PathBuf> = vec![];
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-m") => do_move = true,
+            Some("--pick") => {
+                pick = Some(
+                    crate::utf8_arg(args.next(), "--pick needs the number of a choice")
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .expect("--pick needs the number of a choice"),
+                )
+            }
+            _ => paths.push(arg.into()),
+        }
+    }
+
+    if paths.is_empty() {
+        panic!("resolve needs at least one file or directory under review.");
+    }
+
+    let mut files: Vec<PathBuf> = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        if pick.is_some() {
+            panic!(
+                "--pick applies to files, not a whole directory like {}",
+                path.display()
+            );
+        }
+        let mut found: Vec<PathBuf> = fs::read_dir(&path)
+            .change_context_lazy(|| MyError::ReadFile { path: path.clone() })?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .as_bytes()
+                    .strip_suffix(SIDECAR_SUFFIX.as_bytes())
+                    .map(|file| path.join(OsStr::from_bytes(file)))
+            })
+            .collect();
+        found.sort();
+        files.extend(found);
+    }
+
+    for (index, file) in files.iter().enumerate() {
+        let sidecar = read_sidecar(file)?;
+        let Some(number) = pick else {
+            if index > 0 {
+                println!();
+            }
+            println!(
+                "{} (was {}; {}):",
+                file.display(),
+                sidecar.original.path().display(),
+                sidecar.reason
+            );
+            for (number, choice) in sidecar.choices.iter().enumerate() {
+                println!(
+                    "  {}. {} from {}",
+                    number + 1,
+                    choice.timestamp,
+                    choice.from
+                );
+                println!("     {}", choice.mv);
+            }
+            continue;
+        };
+
+        let Some(choice) = sidecar.choices.get(number - 1) else {
+            panic!(
+                "{} only has {} choice(s), so there's no choice {number}",
+                file.display(),
+                sidecar.choices.len()
+            );
+        };
+        let newpath = destination(file, sidecar.original.path(), &choice.prefix);
+        if newpath.exists() {
+            println!(
+                "ERROR: Not moving file {} because {} already exists.",
+                file.display(),
+                newpath.display()
+            );
+            continue;
+        }
+        if do_move_or_report(file, &newpath, do_move, true)? {
+            let sidecar_file = sidecar_path(file);
+            fs::remove_file(&sidecar_file)
+                .change_context_lazy(|| MyError::Sidecar { path: sidecar_file })?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,762 @@
+//! The places candidate timestamps come from.  Each source looks at a file and produces
+//! candidates with a note of where they came from; handle_image then weighs them up.  Which
+//! sources are used, and in what order, comes from the `sources` setting.  Overrides are a source
+//! too, and come first by default, so that a time zone from one is used by the sources after it.
+
+use jiff::Timestamp;
+use jiff::civil::{Date, DateTime, Time};
+use jiff::{Zoned, tz::TimeZone};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::Path;
+
+use error_stack::{ResultExt, report};
+
+use crate::trace::Step;
+use crate::{MyError, Settings, dedup, native, overrides, patterns};
+
+/// The sources used when the configuration doesn't say otherwise
+pub const DEFAULT_SOURCES: [&str; 4] = ["overrides", "metadata", "file_times", "filename"];
+
+/// A filename regex from the configuration, with example filenames that check-config can try it
+/// on.  In the configuration it's either just the regex, "builtin:NAME" for one from patterns.rs,
+/// or `{ regex: "...", examples: [{ filename: "...", timestamp: "..." }] }`.
+#[derive(Clone, Debug)]
+pub struct FileRegex {
+    pub regex: Regex,
+    pub examples: Vec<RegexExample>,
+    /// The name of the built-in pattern this came from, if it did
+    pub builtin: Option<&'static str>,
+}
+
+impl FileRegex {
+    pub fn from_builtin(builtin: &'static patterns::Builtin) -> FileRegex {
+        FileRegex {
+            regex: Regex::new(builtin.regex).expect("built-in patterns compile"),
+            examples: builtin
+                .examples
+                .iter()
+                .map(|(filename, timestamp)| RegexExample {
+                    filename: filename.to_string(),
+                    timestamp: timestamp.to_string(),
+                })
+                .collect(),
+            builtin: Some(builtin.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct RegexExample {
+    pub filename: String,
+    /// What the filename source should get from it, like "2013-01-01T15:33:37"
+    pub timestamp: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawFileRegex {
+    Plain(String),
+    WithExamples {
+        regex: String,
+        #[serde(default)]
+        examples: Vec<RegexExample>,
+    },
+}
+
+impl<'de> serde::Deserialize<'de> for FileRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (pattern, examples) = match RawFileRegex::deserialize(deserializer)? {
+            RawFileRegex::Plain(pattern) => match pattern.strip_prefix("builtin:") {
+                Some(name) => {
+                    let builtin = patterns::find(name).ok_or_else(|| {
+                        serde::de::Error::custom(format!(
+                            "unknown built-in file regex {:?}; the built-in ones are {}",
+                            pattern,
+                            patterns::names()
+                        ))
+                    })?;
+                    return Ok(FileRegex::from_builtin(builtin));
+                }
+                None => (pattern, vec![]),
+            },
+            RawFileRegex::WithExamples { regex, examples } => (regex, examples),
+        };
+        let regex = Regex::new(&pattern).map_err(|e| {
+            serde::de::Error::custom(format!("bad file regex {:?}: {}", pattern, e))
+        })?;
+        Ok(FileRegex {
+            regex,
+            examples,
+            builtin: None,
+        })
+    }
+}
+
+impl serde::Serialize for FileRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        if let Some(name) = self.builtin {
+            serializer.serialize_str(&format!("builtin:{name}"))
+        } else if self.examples.is_empty() {
+            serializer.serialize_str(self.regex.as_str())
+        } else {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("regex", self.regex.as_str())?;
+            map.serialize_entry("examples", &self.examples)?;
+            map.end()
+        }
+    }
+}
+
+/// Checks that a filename regex has the named groups filename_timestamp needs
+pub fn check_groups(regex: &Regex) -> Result<(), String> {
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    let has = |group: &str| names.contains(&group);
+    if has("sse") || has("msse") {
+        return Ok(());
+    }
+
+    let mut missing = vec![];
+    if !has("year") && !has("yy") {
+        missing.push("year (or yy)");
+    }
+    let mut needed = vec!["month", "day"];
+    if !has("date_only") {
+        needed.extend(["hour", "minute", "second"]);
+    }
+    missing.extend(needed.into_iter().filter(|group| !has(group)));
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "has named groups [{}] but is missing {}; it needs sse or msse, or else year (or yy), month and day, plus hour, minute and second unless it has date_only",
+        names.join(", "),
+        missing.join(", ")
+    ))
+}
+
+#[derive(Clone, Debug)]
+pub enum CandidateTime {
+    /// When the picture was taken according to embedded metadata; these are merged with each
+    /// other and scored.  A -1200 offset means no real time zone was known; see EXIFTOOL_ARGS in
+    /// metadata.rs.
+    Metadata(Zoned),
+    /// A file system timestamp; only the earliest is used, and only if nothing better turns up
+    FileTime(Zoned),
+    /// Wall clock time with no time zone (like from a filename), to be matched up against the
+    /// metadata
+    Civil(DateTime),
+    /// Like Civil, but the time zone is known, like a filename with an offset in it
+    CivilZoned(Zoned),
+    /// Just a date, like a filename with no time in it
+    Date(Date),
+    /// A timestamp from an overrides file, as the wall clock time to use; it decides the file
+    Override(DateTime),
+    /// A time zone from an overrides file, used as home_time_zone by the sources after it
+    HomeTimeZone(String),
+}
+
+impl CandidateTime {
+    /// The wall clock time, in whatever time zone it's in; midnight for a Date, and nothing for
+    /// a time zone
+    pub fn wall_clock(&self) -> Option<DateTime> {
+        match self {
+            CandidateTime::Metadata(ts)
+            | CandidateTime::FileTime(ts)
+            | CandidateTime::CivilZoned(ts) => Some(ts.datetime()),
+            CandidateTime::Civil(datetime) | CandidateTime::Override(datetime) => Some(*datetime),
+            CandidateTime::Date(date) => Some(date.to_datetime(Time::midnight())),
+            CandidateTime::HomeTimeZone(_) => None,
+        }
+    }
+
+    /// The moment in time, if the time zone is known
+    pub fn instant(&self) -> Option<Timestamp> {
+        match self {
+            CandidateTime::Metadata(ts)
+            | CandidateTime::FileTime(ts)
+            | CandidateTime::CivilZoned(ts) => Some(ts.timestamp()),
+            CandidateTime::Civil(_)
+            | CandidateTime::Date(_)
+            | CandidateTime::Override(_)
+            | CandidateTime::HomeTimeZone(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CandidateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CandidateTime::Metadata(ts) => write!(f, "metadata {ts}"),
+            CandidateTime::FileTime(ts) => write!(f, "file time {ts}"),
+            CandidateTime::Civil(datetime) => write!(f, "wall clock {datetime}"),
+            CandidateTime::CivilZoned(ts) => write!(f, "zoned {ts}"),
+            CandidateTime::Date(date) => write!(f, "date {date}"),
+            CandidateTime::Override(datetime) => write!(f, "override {datetime}"),
+            CandidateTime::HomeTimeZone(name) => write!(f, "home time zone {name}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub time: CandidateTime,
+    /// Where it came from, like "EXIF DateTimeOriginal" or the regex that matched
+    pub provenance: String,
+    /// How much to trust it; only meaningful for metadata candidates
+    pub score: u32,
+}
+
+/// Everything a source might want to look at
+pub struct SourceInput<'a> {
+    pub path: &'a Path,
+    /// The path as UTF-8, for matching filename regexes against
+    pub filename: &'a str,
+    /// exiftool's output for the file, grouped by category
+    pub metadata: &'a serde_json::Value,
+    /// The SHA-256 of the file's contents, as dedup::hash_file gives it, once something has
+    /// worked it out; use hash() to get it
+    pub hash: &'a OnceCell<String>,
+    pub settings: &'a Settings,
+}
+
+impl SourceInput<'_> {
+    /// The SHA-256 of the file's contents, hashing the file if that hasn't been done yet
+    pub fn hash(&self) -> error_stack::Result<&str, MyError> {
+        if let Some(hash) = self.hash.get() {
+            return Ok(hash);
+        }
+        let hash = dedup::hash_file(self.path).change_context_lazy(|| MyError::ReadFile {
+            path: self.path.to_path_buf(),
+        })?;
+        Ok(self.hash.get_or_init(|| hash))
+    }
+}
+
+pub trait TimestampSource {
+    /// What this source is called in the `sources` setting
+    fn name(&self) -> &'static str;
+
+    /// Candidate timestamps for the file; anything worth explaining along the way goes in `trace`
+    fn candidates(
+        &self,
+        input: &SourceInput,
+        trace: &mut Vec<Step>,
+    ) -> error_stack::Result<Vec<Candidate>, MyError>;
+}
+
+/// Builds the sources named in the settings, in order, less the disabled ones.  Fails on names we
+/// don't know.
+pub fn from_settings(settings: &Settings) -> Result<Vec<Box<dyn TimestampSource>>, String> {
+    if let Some(unknown) = settings
+        .disabled_sources
+        .iter()
+        .find(|name| !DEFAULT_SOURCES.contains(&name.as_str()))
+    {
+        return Err(format!(
+            "unknown timestamp source {:?} in disabled_sources; the known ones are {}",
+            unknown,
+            DEFAULT_SOURCES.join(", ")
+        ));
+    }
+
+    settings
+        .sources
+        .iter()
+        .filter(|name| !settings.disabled_sources.contains(name))
+        .map(|name| -> Result<Box<dyn TimestampSource>, String> {
+            match name.as_str() {
+                "overrides" => Ok(Box::new(OverridesSource)),
+                "metadata" => Ok(Box::new(MetadataSource)),
+                "file_times" => Ok(Box::new(FileTimesSource)),
+                "filename" => Ok(Box::new(FilenameSource)),
+                other => Err(format!(
+                    "unknown timestamp source {:?}; the known ones are {}",
+                    other,
+                    DEFAULT_SOURCES.join(", ")
+                )),
+            }
+        })
+        .collect()
+}
+
+/// How much each metadata tag is worth; None for a tag we know nothing about
+fn metadata_tag_score(tag: &str) -> Option<u32> {
+    Some(match tag {
+        "Composite SubSecDateTimeOriginal"
+        | "Composite SubSecCreateDate"
+        | "Composite DateTimeCreated"
+        | "Composite DigitalCreationDateTime"
+        | "EXIF DateTimeOriginal" => 5,
+        "Composite SubSecModifyDate"
+        | "EXIF ModifyDate"
+        | "EXIF CreateDate"
+        | "Composite GPSDateTime"
+        | "XMP GPSDateTime"
+        | "XMP CreationDate"
+        | "XMP CreateDate"
+        | "XMP DateCreated"
+        | "XMP ModifyDate"
+        | "ASF CreationDate"
+        | "QuickTime DateTimeOriginal"
+        | "QuickTime ContentCreateDate"
+        | "QuickTime CreateDate"
+        | "QuickTime CreationDate"
+        | "QuickTime CreationDate-und-US"
+        | "QuickTime MediaCreateDate" => 3,
+        "QuickTime MediaModifyDate"
+        | "QuickTime ModifyDate"
+        | "QuickTime TrackCreateDate"
+        | "QuickTime TrackModifyDate"
+        | "RIFF DateTimeOriginal"
+        | "XMP HistoryWhen"
+        | "XMP MetadataDate"
+        | "PNG ModifyDate" => 1,
+        // Only has the *time*
+        "IPTC DigitalCreationTime" | "IPTC TimeCreated" => 0,
+        _ => return None,
+    })
+}
+
+/// Tries to find a time zone for un-time-zoned date tags.  Returns the offset without the colon
+/// (since Jiff doesn't like it) and the tags it came from, or None.  Offset tags that disagree are
+/// an error, since there's no telling which of them is right.
+fn exif_time_zone(
+    path: &Path,
+    metadata: &serde_json::Value,
+) -> error_stack::Result<Option<(String, Vec<String>)>, MyError> {
+    let mut real_exif_tz = "".to_string();
+    let mut tz_tags: Vec<String> = vec![];
+
+    // NOTE: It's possible a good TZ could show up in other tags but not these ones, but we haven't
+    // seen that happen yet
+    let tz_re = Regex::new(r"^[+-]\d\d:?\d\d$").unwrap();
+    let mut conflict = false;
+    for tagname in ["OffsetTimeOriginal", "OffsetTimeDigitized", "OffsetTime"] {
+        if let Some(maybe_tz) = metadata["EXIF"][tagname].as_str()
+            // About the -12 thing, see EXIFTOOL_ARGS in metadata.rs
+            && tz_re.is_match(maybe_tz)
+            && !maybe_tz.starts_with("-12")
+        {
+            // Make sure there's not a conflict between tags
+            conflict |= !real_exif_tz.is_empty() && real_exif_tz != maybe_tz;
+            real_exif_tz = maybe_tz.to_string();
+            tz_tags.push(format!("EXIF {} = {}", tagname, maybe_tz));
+        }
+    }
+
+    if conflict {
+        Err(report!(MyError::ConflictingOffsets {
+            path: path.to_path_buf(),
+            tags: tz_tags,
+        }))
+    } else if real_exif_tz.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((real_exif_tz.replace(":", ""), tz_tags)))
+    }
+}
+
+/// Tags that only have the *date*; these become Date candidates rather than being scored
+const DATE_ONLY_TAGS: [(&str, &str); 2] =
+    [("IPTC", "DigitalCreationDate"), ("IPTC", "DateCreated")];
+
+/// The date-only tags in the metadata, as (group, tag, date)
+fn date_only_tags(metadata: &serde_json::Value) -> Vec<(String, String, Date)> {
+    DATE_ONLY_TAGS
+        .iter()
+        .filter_map(|(group, tag)| {
+            let value = metadata[group][tag].as_str()?;
+            // Either "##DATE## 2019-07-04 ..." or, if exiftool didn't format it, "2019:07:04"
+            let value = value.strip_prefix("##DATE## ").unwrap_or(value);
+            let date = value.get(..10)?.replace(':', "-").parse::<Date>().ok()?;
+            say!("{} {} {}", group, tag, date);
+            Some((group.to_string(), tag.to_string(), date))
+        })
+        .collect()
+}
+
+/// Every date tag in the metadata (other than the DATE_ONLY_TAGS), as (group, tag, timestamp), in
+/// the order exiftool gave them.  `groups` picks which groups to look at.
+fn date_tags(
+    path: &Path,
+    metadata: &serde_json::Value,
+    groups: impl Fn(&str) -> bool,
+) -> error_stack::Result<Vec<(String, String, Zoned)>, MyError> {
+    let real_exif_tz = exif_time_zone(path, metadata)?.map(|(tz, _)| tz);
+    let mut found = vec![];
+    let Some(metadata) = metadata.as_object() else {
+        return Ok(found);
+    };
+
+    for (group, tags) in metadata {
+        let Some(tags) = tags.as_object().filter(|_| groups(group)) else {
+            continue;
+        };
+
+        for (tag, value) in tags {
+            if let Some(valstr) = value.as_str()
+                && valstr.starts_with("##DATE## ")
+                && !DATE_ONLY_TAGS.contains(&(group.as_str(), tag.as_str()))
+            {
+                // Get the date string; these values might have the bogus -1200 TZ but we
+                // don't care about that yet
+                let datestr = match &real_exif_tz {
+                    None => valstr.to_string(),
+                    Some(real_exif_tz) => valstr.replace(" -1200", &format!(" {}", real_exif_tz)),
+                };
+                let timestamp = Zoned::strptime("##DATE## %Y-%m-%d %H:%M:%S %z", &datestr)
+                    .change_context_lazy(|| MyError::BadTag {
+                        path: path.to_path_buf(),
+                        group: group.clone(),
+                        tag: tag.clone(),
+                        value: valstr.trim_start_matches("##DATE## ").to_string(),
+                    })?;
+                say!("{} {} {}", group, tag, timestamp);
+                found.push((group.clone(), tag.clone(), timestamp));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// The first entry in the overrides files that matches the file; see overrides.rs
+pub struct OverridesSource;
+
+impl TimestampSource for OverridesSource {
+    fn name(&self) -> &'static str {
+        "overrides"
+    }
+
+    fn candidates(
+        &self,
+        input: &SourceInput,
+        trace: &mut Vec<Step>,
+    ) -> error_stack::Result<Vec<Candidate>, MyError> {
+        let Some(found) = overrides::find(&input.settings.overrides, input.path, || input.hash())?
+        else {
+            return Ok(vec![]);
+        };
+        let time = match &found.action {
+            overrides::Action::Timestamp(time) => {
+                say!("INFO: Using the override from {}", found.origin);
+                trace.push(Step::new("override", format!("Override {found}")));
+                CandidateTime::Override(time.datetime(input.settings, &input.settings.home_tz()?))
+            }
+            overrides::Action::TimeZone(name) => {
+                say!(
+                    "INFO: Using time zone {} from the override in {}",
+                    name,
+                    found.origin
+                );
+                trace.push(Step::new(
+                    "override",
+                    format!("Override {found}; using it as home_time_zone"),
+                ));
+                CandidateTime::HomeTimeZone(name.clone())
+            }
+        };
+        Ok(vec![Candidate {
+            time,
+            provenance: found.origin.clone(),
+            score: 0,
+        }])
+    }
+}
+
+/// Date tags from exiftool, other than the file system ones
+pub struct MetadataSource;
+
+impl TimestampSource for MetadataSource {
+    fn name(&self) -> &'static str {
+        "metadata"
+    }
+
+    fn candidates(
+        &self,
+        input: &SourceInput,
+        trace: &mut Vec<Step>,
+    ) -> error_stack::Result<Vec<Candidate>, MyError> {
+        let real_exif_tz = exif_time_zone(input.path, input.metadata)?;
+        say!(
+            "real_exif_tz: {}",
+            real_exif_tz.as_ref().map_or("", |(tz, _)| tz.as_str())
+        );
+        if let Some((tz, tz_tags)) = real_exif_tz {
+            trace.push(
+                Step::new(
+                    "exif_time_zone",
+                    format!(
+                        "Using time zone {} for metadata dates that don't have one",
+                        tz
+                    ),
+                )
+                .inputs(tz_tags),
+            );
+        }
+
+        // We don't care about the color profile at all; why does it even have a timestamp??
+        let tags = date_tags(input.path, input.metadata, |group| {
+            group != "File" && group != "ICC_Profile"
+        })?;
+
+        let mut candidates: Vec<Candidate> = tags
+            .into_iter()
+            .map(|(group, tag, timestamp)| {
+                let provenance = format!("{} {}", group, tag);
+                // A new exiftool (or the native reader) can come up with tags we haven't seen
+                let score = metadata_tag_score(&provenance).unwrap_or_else(|| {
+                    say!(
+                        "WARNING: Tag {} in {} is unknown, so it scores 0.",
+                        provenance,
+                        input.path.display()
+                    );
+                    trace.push(
+                        Step::new(
+                            "unknown_tag",
+                            "Not a tag we know how much to trust; scores 0",
+                        )
+                        .inputs([format!("{} = {}", provenance, timestamp)]),
+                    );
+                    0
+                });
+                Candidate {
+                    time: CandidateTime::Metadata(timestamp),
+                    score,
+                    provenance,
+                }
+            })
+            .collect();
+        candidates.extend(
+            date_only_tags(input.metadata)
+                .into_iter()
+                .map(|(group, tag, date)| Candidate {
+                    time: CandidateTime::Date(date),
+                    provenance: format!("{} {}", group, tag),
+                    score: 0,
+                }),
+        );
+
+        Ok(candidates)
+    }
+}
+
+/// The file system timestamps, as reported by exiftool's File group
+pub struct FileTimesSource;
+
+impl TimestampSource for FileTimesSource {
+    fn name(&self) -> &'static str {
+        "file_times"
+    }
+
+    fn candidates(
+        &self,
+        input: &SourceInput,
+        _trace: &mut Vec<Step>,
+    ) -> error_stack::Result<Vec<Candidate>, MyError> {
+        let tags = date_tags(input.path, input.metadata, |group| group == "File")?;
+
+        Ok(tags
+            .into_iter()
+            .map(|(group, tag, timestamp)| Candidate {
+                time: CandidateTime::FileTime(timestamp),
+                provenance: format!("{} {}", group, tag),
+                score: 0,
+            })
+            .collect())
+    }
+}
+
+/// Timestamps in the filename, found with the `file_regexes` setting
+pub struct FilenameSource;
+
+impl TimestampSource for FilenameSource {
+    fn name(&self) -> &'static str {
+        "filename"
+    }
+
+    fn candidates(
+        &self,
+        input: &SourceInput,
+        _trace: &mut Vec<Step>,
+    ) -> error_stack::Result<Vec<Candidate>, MyError> {
+        let filename = input.filename;
+        let mut candidates = vec![];
+
+        let regexes = input
+            .settings
+            .file_regexes
+            .iter()
+            .chain(&input.settings.extra_file_regexes);
+        for file_regex in regexes {
+            let regex = &file_regex.regex;
+            if let Some(time) = filename_timestamp(regex, filename, input.settings)? {
+                candidates.push(Candidate {
+                    time,
+                    provenance: match file_regex.builtin {
+                        Some(name) => format!("builtin:{name}"),
+                        None => regex.to_string(),
+                    },
+                    score: 0,
+                });
+            }
+        }
+
+        Ok(candidates)
+    }
+}
+
+/// The timestamp a filename regex finds in a filename, if it matches.  These named groups mean
+/// something:
+///
+///   year, month, day, hour, minute, second: the obvious
+///   yy: a two digit year; below two_digit_year_pivot it's 20yy, otherwise 19yy
+///   ampm: "am" or "pm" (or just "a" or "p"), making hour a 12 hour clock
+///   subsec: digits of fractions of a second (ms is the same thing, for older regexes)
+///   offset: the time zone offset, like "+0200", "-05:00" or "Z"
+///   tz: an IANA time zone name, like "Europe/Berlin", if there's no offset
+///   sse, msse: seconds or milliseconds since the epoch, instead of all of the above
+///   date_only: marks a regex that has a date but no time; it can match nothing, like
+///     (?P<date_only>)
+pub fn filename_timestamp(
+    regex: &Regex,
+    filename: &str,
+    settings: &Settings,
+) -> error_stack::Result<Option<CandidateTime>, MyError> {
+    let Some(caps) = regex.captures(filename) else {
+        return Ok(None);
+    };
+
+    let group = |name: &str| caps.name(name).map(|m| m.as_str());
+    let bad = |part: &'static str, value: &str| MyError::BadFilenameTimestamp {
+        regex: regex.to_string(),
+        filename: filename.to_string(),
+        part,
+        value: value.to_string(),
+    };
+    let number = |name: &'static str| -> error_stack::Result<Option<i64>, MyError> {
+        group(name)
+            .map(|digits| {
+                digits
+                    .parse::<i64>()
+                    .change_context_lazy(|| bad(name, digits))
+            })
+            .transpose()
+    };
+    let no_groups = || -> ! {
+        panic!(
+            "ERROR: Regex {} matched {} but without producing any expected capture groups.",
+            regex, filename
+        )
+    };
+
+    // Some (Wyze) use (milli)seconds since the epoch; those are the same moment everywhere, so
+    // they're turned into home time and then matched like any other wall clock time
+    let epoch = match (number("sse")?, number("msse")?) {
+        (Some(sse), _) => Some(Timestamp::from_second(sse)),
+        (None, Some(msse)) => Some(Timestamp::from_millisecond(msse)),
+        (None, None) => None,
+    };
+    if let Some(timestamp) = epoch {
+        let zoned = timestamp
+            .change_context_lazy(|| {
+                bad(
+                    "seconds since the epoch",
+                    group("sse").or(group("msse")).unwrap_or_default(),
+                )
+            })?
+            .to_zoned(settings.home_tz()?);
+        say!("filename timestamp: {:#?}", zoned.datetime());
+        return Ok(Some(CandidateTime::Civil(zoned.datetime())));
+    }
+
+    let year = match (number("year")?, number("yy")?) {
+        (Some(year), _) => year,
+        (None, Some(yy)) if yy < settings.two_digit_year_pivot => 2000 + yy,
+        (None, Some(yy)) => 1900 + yy,
+        (None, None) => no_groups(),
+    };
+    let (Some(month), Some(day)) = (number("month")?, number("day")?) else {
+        no_groups()
+    };
+    let datestr = format!("{:04}-{:02}-{:02}", year, month, day);
+    if group("date_only").is_some() {
+        let date = datestr
+            .parse::<Date>()
+            .change_context_lazy(|| bad("date", &datestr))?;
+        say!("filename date: {}", date);
+        return Ok(Some(CandidateTime::Date(date)));
+    }
+
+    let (Some(mut hour), Some(minute), Some(second)) =
+        (number("hour")?, number("minute")?, number("second")?)
+    else {
+        no_groups()
+    };
+    if let Some(ampm) = group("ampm") {
+        // 12am is midnight and 12pm is noon
+        let pm = ampm.to_ascii_lowercase().starts_with('p');
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    // Nanoseconds, from however many digits there are
+    let nanos = match group("subsec").or(group("ms")) {
+        Some(digits) => {
+            // \d can match digits from other scripts, which jiff doesn't take
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(report!(bad("subsec", digits)));
+            }
+            format!("{:0<9}", &digits[..digits.len().min(9)])
+        }
+        None => "0".repeat(9),
+    };
+    let datetimestr = format!(
+        "{}T{:02}:{:02}:{:02}.{}",
+        datestr, hour, minute, second, nanos
+    );
+    let datetime = datetimestr
+        .parse::<DateTime>()
+        .change_context_lazy(|| bad("date and time", &datetimestr))?;
+
+    let time_zone = if let Some(offset) = group("offset") {
+        let offset = native::parse_offset(offset).ok_or_else(|| report!(bad("offset", offset)))?;
+        Some(TimeZone::fixed(offset))
+    } else if let Some(name) = group("tz") {
+        Some(TimeZone::get(name).change_context_lazy(|| bad("time zone", name))?)
+    } else if filename.contains("PXL_") {
+        // FIXME: It is goofy that this is hardcoded, but it's the only file type
+        // where I've seen this issue: my Pixel phone consistently writes out
+        // filenames with the time in UTC.  Like an epoch time, it's matched as home time.
+        let utc = datetime
+            .to_zoned(TimeZone::UTC)
+            .change_context_lazy(|| MyError::TimeArithmetic(format!("put {datetime} in UTC")))?;
+        let datetime = utc.with_time_zone(settings.home_tz()?).datetime();
+        say!("filename timestamp: {:#?}", datetime);
+        return Ok(Some(CandidateTime::Civil(datetime)));
+    } else {
+        None
+    };
+
+    match time_zone {
+        Some(time_zone) => {
+            let zoned = datetime
+                .to_zoned(time_zone.clone())
+                .change_context_lazy(|| {
+                    MyError::TimeArithmetic(format!(
+                        "put {datetime} in {}",
+                        time_zone.iana_name().unwrap_or("its time zone")
+                    ))
+                })?;
+            say!("filename timestamp: {}", zoned);
+            Ok(Some(CandidateTime::CivilZoned(zoned)))
+        }
+        None => {
+            say!("filename timestamp: {:#?}", datetime);
+            Ok(Some(CandidateTime::Civil(datetime)))
+        }
+    }
+}
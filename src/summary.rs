@@ -0,0 +1,120 @@
+//! Counting up what happened to every file in a run, so that the end of the output (and the exit
+//! status) says whether the run was clean without anyone having to read all of it.
+
+use std::fmt;
+
+/// What happened to one file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileResult {
+    Renamed,
+    /// Decided, but without -m nothing was moved
+    DryRun,
+    /// Decided, but less confidently than --min-confidence asks for
+    LowConfidence,
+    /// No single timestamp could be picked
+    Ambiguous,
+    /// Ambiguous, and moved into --review-dir
+    Quarantined,
+    NotMedia,
+    /// Already has a prefix, so it was left out when scanning a directory
+    AlreadyPrefixed,
+    /// Something went wrong reading or moving it
+    Error,
+    /// Where it would have gone, something already was
+    Collision,
+}
+
+/// Outcome classes that --fail-on can make the exit status non-zero for; errors and collisions
+/// always do
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailOn {
+    Ambiguous,
+    LowConfidence,
+    NotMedia,
+}
+
+impl FailOn {
+    pub fn parse(name: &str) -> Result<FailOn, String> {
+        match name {
+            "ambiguous" => Ok(FailOn::Ambiguous),
+            "low-confidence" => Ok(FailOn::LowConfidence),
+            "not-media" => Ok(FailOn::NotMedia),
+            _ => Err(format!(
+                "--fail-on takes ambiguous, low-confidence or not-media, not {name:?}"
+            )),
+        }
+    }
+}
+
+/// Exit statuses, one per class of trouble; when there's more than one kind, the first of these
+/// that applies wins
+pub const EXIT_ERRORS: i32 = 2;
+pub const EXIT_COLLISIONS: i32 = 3;
+pub const EXIT_AMBIGUOUS: i32 = 4;
+pub const EXIT_LOW_CONFIDENCE: i32 = 5;
+pub const EXIT_NOT_MEDIA: i32 = 6;
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub renamed: usize,
+    pub dry_run: usize,
+    pub low_confidence: usize,
+    pub ambiguous: usize,
+    pub quarantined: usize,
+    pub not_media: usize,
+    pub already_prefixed: usize,
+    pub errors: usize,
+    pub collisions: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, result: FileResult) {
+        *match result {
+            FileResult::Renamed => &mut self.renamed,
+            FileResult::DryRun => &mut self.dry_run,
+            FileResult::LowConfidence => &mut self.low_confidence,
+            FileResult::Ambiguous => &mut self.ambiguous,
+            FileResult::Quarantined => &mut self.quarantined,
+            FileResult::NotMedia => &mut self.not_media,
+            FileResult::AlreadyPrefixed => &mut self.already_prefixed,
+            FileResult::Error => &mut self.errors,
+            FileResult::Collision => &mut self.collisions,
+        } += 1;
+    }
+
+    pub fn exit_code(&self, fail_on: &[FailOn]) -> i32 {
+        if self.errors > 0 {
+            EXIT_ERRORS
+        } else if self.collisions > 0 {
+            EXIT_COLLISIONS
+        } else if self.ambiguous + self.quarantined > 0 && fail_on.contains(&FailOn::Ambiguous) {
+            EXIT_AMBIGUOUS
+        } else if self.low_confidence > 0 && fail_on.contains(&FailOn::LowConfidence) {
+            EXIT_LOW_CONFIDENCE
+        } else if self.not_media > 0 && fail_on.contains(&FailOn::NotMedia) {
+            EXIT_NOT_MEDIA
+        } else {
+            0
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Summary:")?;
+        for (label, count) in [
+            ("renamed", self.renamed),
+            ("decided (dry run, not moved)", self.dry_run),
+            ("below --min-confidence", self.low_confidence),
+            ("ambiguous", self.ambiguous),
+            ("ambiguous, moved for review", self.quarantined),
+            ("not media", self.not_media),
+            ("skipped, already prefixed", self.already_prefixed),
+            ("errors", self.errors),
+            ("collisions", self.collisions),
+        ] {
+            writeln!(f, "  {label:<30} {count}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! A record of how a decision was reached, rule by rule, for the explain command.
+
+use serde::Serialize;
+
+use crate::{Decision, Outcome};
+
+/// One rule firing while deciding on a file's timestamp
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    /// Short fixed name for the rule, like "twelve_hour_merge"
+    pub rule: &'static str,
+    /// What happened, in words
+    pub detail: String,
+    /// Whatever the rule looked at: tags and their values, regexes, candidates
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    /// Candidates the rule created
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    /// Candidates the rule got rid of
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// Candidates whose score the rule changed, as they were afterwards
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rescored: Vec<String>,
+}
+
+impl Step {
+    pub fn new(rule: &'static str, detail: impl Into<String>) -> Self {
+        Step {
+            rule,
+            detail: detail.into(),
+            inputs: vec![],
+            added: vec![],
+            removed: vec![],
+            rescored: vec![],
+        }
+    }
+
+    pub fn inputs(mut self, items: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.inputs = items.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    pub fn added(mut self, items: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.added = items.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    pub fn removed(mut self, items: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.removed = items.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    pub fn rescored(mut self, items: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.rescored = items.into_iter().map(|x| x.to_string()).collect();
+        self
+    }
+}
+
+/// The decision and its trace, in a form that's easy to turn into JSON
+#[derive(Serialize)]
+struct Explanation<'a> {
+    file: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    basis: Option<crate::Basis>,
+    /// From 0 to 100; see Basis::confidence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<u32>,
+    steps: &'a [Step],
+}
+
+fn explanation(decision: &Decision) -> Explanation<'_> {
+    let (status, prefix, basis) = match decision.outcome {
+        Outcome::NotMedia => ("not_media", None, None),
+        Outcome::Undecided { .. } => ("undecided", None, None),
+        Outcome::Decided { ts, basis } => ("decided", Some(crate::prefix_for(ts)), Some(basis)),
+    };
+    Explanation {
+        file: decision.filename.to_string_lossy().into_owned(),
+        status,
+        prefix,
+        basis,
+        confidence: basis.map(|basis| basis.confidence()),
+        steps: &decision.trace,
+    }
+}
+
+pub fn to_json(decision: &Decision) -> String {
+    serde_json::to_string_pretty(&explanation(decision)).unwrap()
+}
+
+pub fn to_text(decision: &Decision) -> String {
+    let explanation = explanation(decision);
+    let mut output = format!("Decision trace for {}\n\n", explanation.file);
+
+    for (number, step) in explanation.steps.iter().enumerate() {
+        output += &format!("{:>3}. {}: {}\n", number + 1, step.rule, step.detail);
+        for (label, items) in [
+            ("input", &step.inputs),
+            ("added", &step.added),
+            ("removed", &step.removed),
+            ("rescored", &step.rescored),
+        ] {
+            for item in items {
+                output += &format!("       {:<8} {}\n", label, item);
+            }
+        }
+    }
+
+    output += &format!("\nResult: {}", explanation.status);
+    if let (Some(prefix), Some(basis)) = (explanation.prefix, explanation.basis) {
+        output += &format!(
+            ", prefix {} ({:?}), confidence {}",
+            prefix,
+            basis,
+            basis.confidence()
+        );
+    }
+    output += "\n";
+    output
+}
@@ -0,0 +1,256 @@
+//! `picnamion watch DIR`: renames files as they arrive in DIR (say, a folder a phone syncs into),
+//! once they've stopped changing.  Files that can't be decided with enough confidence go into
+//! DIR/needs-review/, with sidecars for `picnamion resolve`, and what happened to every file goes
+//! in a log.
+
+use crate::metadata::MetadataBackend;
+use crate::review;
+use crate::summary::FileResult;
+use crate::{
+    Configuration, MyError, Options, Outcome, already_prefixed, apply_decision, decide, isolated,
+    prefix_for, prefixed_path, settings_for_file, utf8_arg,
+};
+use error_stack::ResultExt;
+use inotify::{Inotify, WatchMask};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where files that need a person to look at them go, under the watched directory
+const NEEDS_REVIEW: &str = "needs-review";
+
+/// How often to check for settled files
+const POLL: Duration = Duration::from_secs(1);
+
+/// Appends timestamped lines to the log file, and echoes them to stdout
+struct Log {
+    path: PathBuf,
+    file: File,
+}
+
+impl Log {
+    fn write(&mut self, message: &str) {
+        let now = jiff::Zoned::now().strftime("%Y-%m-%d %H:%M:%S %z");
+        println!("{message}");
+        if let Err(e) = writeln!(self.file, "{now} {message}") {
+            println!(
+                "WARNING: Couldn't write to the log {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+pub fn run(
+    mut args: impl Iterator<Item = OsString>,
+    configuration: &Configuration,
+    backend: &mut dyn MetadataBackend,
+) -> error_stack::Result<(), MyError> {
+    let mut dir: Option<PathBuf> = None;
+    let mut log_path = PathBuf::from("picnamion-watch.log");
+    let mut options = Options {
+        do_move: true,
+        ..Options::default()
+    };
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--log") => log_path = args.next().expect("--log needs a file argument").into(),
+            Some("--min-confidence") => {
+                options.min_confidence =
+                    utf8_arg(args.next(), "--min-confidence needs a number from 0 to 100")
+                        .parse()
+                        .ok()
+                        .filter(|n| *n <= 100)
+                        .expect("--min-confidence needs a number from 0 to 100")
+            }
+            _ if dir.is_none() => dir = Some(arg.into()),
+            _ => panic!("watch takes just one directory."),
+        }
+    }
+    let dir = dir.expect("watch needs a directory to watch.");
+
+    let (settings, _) = settings_for_file(configuration, &dir);
+    let settle = Duration::from_secs(settings.watch_settle_seconds);
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .unwrap_or_else(|e| panic!("Couldn't open the log {}: {e}", log_path.display()));
+    let mut log = Log {
+        path: log_path,
+        file,
+    };
+
+    let mut inotify =
+        Inotify::init().change_context_lazy(|| MyError::Watch { path: dir.clone() })?;
+    inotify
+        .watches()
+        .add(
+            &dir,
+            WatchMask::CREATE | WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO,
+        )
+        .change_context_lazy(|| MyError::Watch { path: dir.clone() })?;
+
+    // When each file waiting to be handled last changed; anything that was already here counts
+    // as having just changed, in case it's still being written
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    for entry in fs::read_dir(&dir).change_context_lazy(|| MyError::Watch { path: dir.clone() })? {
+        let path = entry
+            .change_context_lazy(|| MyError::Watch { path: dir.clone() })?
+            .path();
+        if wanted(&path, &log.path) {
+            pending.insert(path, Instant::now());
+        }
+    }
+
+    log.write(&format!(
+        "INFO: Watching {}, handling files once they've been left alone for {}s.",
+        dir.display(),
+        settle.as_secs()
+    ));
+
+    let mut buffer = [0; 4096];
+    loop {
+        match inotify.read_events(&mut buffer) {
+            Ok(events) => {
+                for event in events {
+                    if let Some(name) = event.name {
+                        let path = dir.join(name);
+                        if wanted(&path, &log.path) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                return Err(e).change_context_lazy(|| MyError::Watch { path: dir.clone() });
+            }
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            pending.remove(&path);
+            // Directories, and files that went away again, aren't ours to handle
+            if path.is_file() {
+                handle(&path, &dir, configuration, backend, &options, &mut log);
+            }
+        }
+
+        thread::sleep(POLL);
+    }
+}
+
+/// Whether a file showing up in the watched directory is one to handle: not a dotfile (Syncthing
+/// writes into .syncthing.NAME.tmp), not some other partial download ending in .tmp, not already
+/// prefixed (which includes everything we rename), and not named like our own log
+fn wanted(path: &Path, log: &Path) -> bool {
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    let partial = name.starts_with('.') || name.ends_with(".tmp");
+    !partial && !already_prefixed(path) && path.file_name() != log.file_name()
+}
+
+/// Decides on one settled file and renames it, or moves it into needs-review/, or leaves it alone
+/// if it isn't media; nothing that goes wrong here stops the watching
+fn handle(
+    path: &Path,
+    dir: &Path,
+    configuration: &Configuration,
+    backend: &mut dyn MetadataBackend,
+    options: &Options,
+    log: &mut Log,
+) {
+    println!("\n\n********************** path: {}\n", path.display());
+    let decision = match isolated(|| decide(configuration, path, backend)) {
+        Ok(decision) => decision,
+        Err(e) => {
+            log.write(&format!(
+                "ERROR: Failed to handle file {}: {:?}",
+                path.display(),
+                e
+            ));
+            return;
+        }
+    };
+
+    let review_because = match decision.outcome {
+        Outcome::Decided { basis, .. } if basis.confidence() < options.min_confidence => {
+            Some(format!(
+                "its confidence ({:?}, {}) is below {}",
+                basis,
+                basis.confidence(),
+                options.min_confidence
+            ))
+        }
+        Outcome::Undecided { .. } => Some("no single timestamp could be picked".to_string()),
+        Outcome::Decided { .. } | Outcome::NotMedia => None,
+    };
+    if let Some(reason) = review_because {
+        let review_dir = dir.join(NEEDS_REVIEW);
+        match isolated(|| review::quarantine(&decision, &review_dir, &reason, true)) {
+            Ok(FileResult::Collision) => log.write(&format!(
+                "ERROR: Not moving {} for review because {} already has one by that name.",
+                path.display(),
+                review_dir.display()
+            )),
+            Ok(_) => log.write(&format!(
+                "WARNING: Moved {} to {} because {reason}.",
+                path.display(),
+                review_dir.display()
+            )),
+            Err(e) => log.write(&format!(
+                "ERROR: Failed to move file {} for review: {:?}",
+                path.display(),
+                e
+            )),
+        }
+        return;
+    }
+
+    match isolated(|| apply_decision(&decision, options, false)) {
+        Ok(FileResult::Renamed) => {
+            if let Outcome::Decided { ts, basis } = decision.outcome {
+                log.write(&format!(
+                    "INFO: Renamed {} to {} ({:?}, confidence {})",
+                    path.display(),
+                    prefixed_path(path, &prefix_for(ts)).display(),
+                    basis,
+                    basis.confidence()
+                ));
+            }
+        }
+        Ok(FileResult::NotMedia) => {
+            log.write(&format!("INFO: Left {} alone; not media.", path.display()))
+        }
+        Ok(FileResult::Collision) => log.write(&format!(
+            "ERROR: Not renaming {} because a file with its new name already exists.",
+            path.display()
+        )),
+        Ok(result) => log.write(&format!("INFO: {}: {:?}", path.display(), result)),
+        Err(e) => match e.current_context() {
+            MyError::Rotate { path: newpath } => log.write(&format!(
+                "ERROR: Renamed {} to {}, but failed to rotate it: {:?}",
+                path.display(),
+                newpath.display(),
+                e
+            )),
+            _ => log.write(&format!(
+                "ERROR: Failed to move file {}: {:?}",
+                path.display(),
+                e
+            )),
+        },
+    }
+}
@@ -17,30 +17,396 @@ setup() {
 DIR="$( cd "$( dirname "$BATS_TEST_FILENAME" )" >/dev/null 2>&1 && pwd )"
 
 cd $DIR/../
-# Save original IFS
-OIFS="$IFS"
-# Set IFS to only newline
-IFS=$'\n'
-
-# Run our test for every file
-for file in $(find test_data/ -type f \! -name '*.prefix' \! -name '*.time')
-do
-  bats_test_function --description "file test for $file" -- output_check "$file"
-done
-
-# Restore original IFS
-IFS="$OIFS"
-
-output_check() {
-  file="$1"
-  echo "checking file: $file" 1>&2
-  # Set the file to its stored modification time
-  touch -d @"$(cat "$file.time")" "$file"
-  run -0 ./target/debug/picnamion "$file"
-  if [[ "$(cat "$file.prefix")" == "NONE" ]]
+
+# Every file in test_data is checked against its golden decision by tests/golden.rs (cargo test)
+
+@test "organize dry run reports the date-based destination" {
+  touch -d @"$(cat "test_data/IMG_20130101_153337.jpg.time")" "test_data/IMG_20130101_153337.jpg"
+  run -0 ./target/debug/picnamion --organize '{year}/{month}' --organize-root /photos test_data/IMG_20130101_153337.jpg
+  assert_output --partial "INFO: Would move file test_data/IMG_20130101_153337.jpg to /photos/2013/01/2013-01-01_15-33-37--IMG_20130101_153337.jpg"
+}
+
+@test "organize with --keep-name leaves the filename alone" {
+  touch -d @"$(cat "test_data/IMG_20130101_153337.jpg.time")" "test_data/IMG_20130101_153337.jpg"
+  run -0 ./target/debug/picnamion --organize '{year}/{month}/{day}' --organize-root /photos --keep-name test_data/IMG_20130101_153337.jpg
+  assert_output --partial "INFO: Would move file test_data/IMG_20130101_153337.jpg to /photos/2013/01/01/IMG_20130101_153337.jpg"
+}
+
+@test "exact duplicates are reported and share a decision" {
+  for file in test_data/IMG_20250410_0844363.jpg test_data/IMG_20250411_1144363.jpg test_data/IMG_20250411_1444363.jpg
+  do
+    touch -d @"$(cat "$file.time")" "$file"
+  done
+  run -0 ./target/debug/picnamion test_data/IMG_20250410_0844363.jpg test_data/IMG_20250411_1144363.jpg test_data/IMG_20250411_1444363.jpg
+  assert_output --partial "INFO: These files are exact duplicates of each other"
+  assert_output --partial "INFO: Using the decision for"
+  assert_output --partial "for its duplicate test_data/IMG_20250410_0844363.jpg as well"
+}
+
+@test "explain prints the decision trace" {
+  touch -d @"$(cat "test_data/IMG_20130101_153337.jpg.time")" "test_data/IMG_20130101_153337.jpg"
+  run -0 ./target/debug/picnamion explain test_data/IMG_20130101_153337.jpg
+  assert_output --partial "Decision trace for test_data/IMG_20130101_153337.jpg"
+  assert_output --partial "filename_timestamp: A filename regex matched"
+  assert_output --partial "Result: decided, prefix 2013-01-01_15-33-37--"
+  refute_output --partial "INFO: Prefix determined:"
+}
+
+@test "explain --json prints only JSON" {
+  touch -d @"$(cat "test_data/IMG_20130101_153337.jpg.time")" "test_data/IMG_20130101_153337.jpg"
+  run -0 ./target/debug/picnamion explain --json test_data/IMG_20130101_153337.jpg
+  assert_output --partial '"status": "decided"'
+  assert_output --partial '"prefix": "2013-01-01_15-33-37--"'
+  echo "$output" | python3 -m json.tool > /dev/null
+}
+
+@test "explain shows exact and near duplicates among the files it's given" {
+  cp test_data/photo_2024-01-10_19-19-37.jpg "$BATS_TEST_TMPDIR/"
+  cp test_data/photo_2024-01-10_19-19-37.jpg "$BATS_TEST_TMPDIR/copy.jpg"
+  # Trailing bytes change the hash but not the picture
+  cp test_data/photo_2024-01-10_19-19-37.jpg "$BATS_TEST_TMPDIR/resaved.jpg"
+  printf 'trailing' >> "$BATS_TEST_TMPDIR/resaved.jpg"
+  run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/photo_2024-01-10_19-19-37.jpg" "$BATS_TEST_TMPDIR/copy.jpg" "$BATS_TEST_TMPDIR/resaved.jpg"
+  assert_output --partial "exact_duplicate: Identical contents to $BATS_TEST_TMPDIR/photo_2024-01-10_19-19-37.jpg"
+  assert_output --partial "(perceptual hash distance 0 of 64 bits)"
+  assert_output --partial "(NearDuplicate { distance: 0 })"
+  refute_output --partial "INFO: These files are exact duplicates"
+}
+
+@test "an undated near duplicate borrows the prefix of a file renamed in an earlier run" {
+  mkdir "$BATS_TEST_TMPDIR/album"
+  cp test_data/photo_2024-01-10_19-19-37.jpg "$BATS_TEST_TMPDIR/album/2024-01-10_19-19-37--photo.jpg"
+  cp test_data/photo_2024-01-10_19-19-37.jpg "$BATS_TEST_TMPDIR/album/resaved.jpg"
+  printf 'trailing' >> "$BATS_TEST_TMPDIR/album/resaved.jpg"
+  run -0 ./target/debug/picnamion -m "$BATS_TEST_TMPDIR/album"
+  assert_output --partial "resaved.jpg looks like a re-encoded copy of $BATS_TEST_TMPDIR/album/2024-01-10_19-19-37--photo.jpg"
+  assert [ -e "$BATS_TEST_TMPDIR/album/2024-01-10_19-19-37--resaved.jpg" ]
+}
+
+@test "native and exiftool metadata backends agree" {
+  if ! command -v exiftool > /dev/null
+  then
+    skip "exiftool isn't installed"
+  fi
+  IFS=$'\n'
+  for file in $(find test_data/ -type f \! -name '*.prefix' \! -name '*.time' \! -name '*.golden.json')
+  do
+    touch -d @"$(cat "$file.time")" "$file"
+    native="$(PICNAMION_METADATA_BACKEND=native ./target/debug/picnamion "$file" | grep 'INFO: Prefix determined:')"
+    exiftool="$(PICNAMION_METADATA_BACKEND=exiftool ./target/debug/picnamion "$file" | grep 'INFO: Prefix determined:')"
+    assert_equal "$native" "$exiftool"
+  done
+}
+
+@test "the native backend survives corrupt dates and box sizes" {
+  # A multi-byte character where the date and time meet in DateTimeOriginal
+  python3 -c "import sys; d = open(sys.argv[1], 'rb').read(); open(sys.argv[2], 'wb').write(d.replace(b'2012:02:13 19:34:35', b'2012:02:1\xc3\xa919:34:35'))" "test_data/2012-02-13 19.34.36.jpg" "$BATS_TEST_TMPDIR/bad_date.jpg"
+  PICNAMION_METADATA_BACKEND=native run -0 ./target/debug/picnamion "$BATS_TEST_TMPDIR/bad_date.jpg"
+  refute_output --partial "ERROR: Failed to handle file"
+  # A box claiming to run past the end of any possible file
+  python3 -c "import struct, sys; open(sys.argv[1], 'wb').write(struct.pack('>I4s4sI', 16, b'ftyp', b'isom', 0x200) + struct.pack('>I4sQ', 1, b'free', 2**64 - 8) + bytes(16))" "$BATS_TEST_TMPDIR/bad_size.mp4"
+  PICNAMION_METADATA_BACKEND=native run -0 ./target/debug/picnamion "$BATS_TEST_TMPDIR/bad_size.mp4"
+  refute_output --partial "ERROR: Failed to handle file"
+}
+
+@test "the native backend leaves ISO-BMFF files that aren't MP4 or MOV to exiftool" {
+  python3 -c "import struct, sys; open(sys.argv[1], 'wb').write(struct.pack('>I4s4sI', 16, b'ftyp', b'M4A ', 0) + bytes(16))" "$BATS_TEST_TMPDIR/song_20190704_153337.m4a"
+  PICNAMION_METADATA_BACKEND=native run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/song_20190704_153337.m4a"
+  assert_output --partial "Result: not_media"
+}
+
+@test "disagreeing EXIF offset tags are an error that names them and the file" {
+  # The first of the three offsets in this file is OffsetTime's
+  python3 -c "import sys; d = bytearray(open(sys.argv[1], 'rb').read()); d[828:834] = b'+02:00'; open(sys.argv[2], 'wb').write(d)" test_data/PXL_20250709_002352285.jpg "$BATS_TEST_TMPDIR/PXL_20250709_002352285.jpg"
+  run -2 ./target/debug/picnamion "$BATS_TEST_TMPDIR/PXL_20250709_002352285.jpg"
+  assert_output --partial "the EXIF offset tags in $BATS_TEST_TMPDIR/PXL_20250709_002352285.jpg disagree: EXIF OffsetTimeOriginal = -07:00, EXIF OffsetTimeDigitized = -07:00, EXIF OffsetTime = +02:00"
+  refute_output --partial "panicked"
+}
+
+@test "config show --origin says where each setting came from" {
+  PICNAMION_NEAR_DUPLICATE_DISTANCE=9 run -0 ./target/debug/picnamion --set metadata_backend=native config show --origin
+  assert_output --partial 'near_duplicate_distance = 9    # from environment (PICNAMION_NEAR_DUPLICATE_DISTANCE)'
+  assert_output --partial 'metadata_backend = "native"    # from command line (--set metadata_backend)'
+  assert_output --partial 'exiftool_path = "exiftool"    # from built-in defaults'
+}
+
+@test "a .picnamion.json5 changes settings for its directory" {
+  mkdir -p "$BATS_TEST_TMPDIR/japan"
+  cp test_data/IMG_20130101_153337.jpg "$BATS_TEST_TMPDIR/japan/"
+  touch -d @"$(cat "test_data/IMG_20130101_153337.jpg.time")" "$BATS_TEST_TMPDIR/japan/IMG_20130101_153337.jpg"
+  echo '{ force_time_zone: "Asia/Tokyo" }' > "$BATS_TEST_TMPDIR/japan/.picnamion.json5"
+  run -0 ./target/debug/picnamion "$BATS_TEST_TMPDIR/japan/IMG_20130101_153337.jpg" test_data/IMG_20130101_153337.jpg
+  assert_output --partial "INFO: Prefix determined: 2013-01-02_08-33-37--"
+  assert_output --partial "INFO: Prefix determined: 2013-01-01_15-33-37--"
+}
+
+@test "check-config passes on the shipped configuration" {
+  run -0 ./target/debug/picnamion check-config
+  assert_output --partial "INFO: Configuration is OK"
+}
+
+@test "check-config catches missing groups and failing examples" {
+  run -1 ./target/debug/picnamion --set 'extra_file_regexes=["IMG(?P<year>[0-9]{4})", {"regex": "x(?P<sse>[0-9]{10})", "examples": [{"filename": "y.jpg", "timestamp": "2023-11-14T22:13:20"}]}]' check-config
+  assert_output --partial "has named groups [year] but is missing month, day, hour, minute, second"
+  assert_output --partial "doesn't match its example \"y.jpg\""
+}
+
+@test "filename regexes can use yy, ampm, subsec, offset and date_only" {
+  run -0 ./target/debug/picnamion --set 'extra_file_regexes=[{"regex": "Cam_(?P<yy>[0-9]{2})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_(?P<hour>[0-9]{1,2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})(?P<ampm>[ap]m)_(?P<subsec>[0-9]+)(?P<offset>[+-][0-9]{4})", "examples": [{"filename": "Cam_990704_120102am_25+0200.jpg", "timestamp": "1999-07-04T00:01:02.25"}, {"filename": "Cam_240704_120102pm_5-0700.jpg", "timestamp": "2024-07-04T12:01:02.5"}]}, {"regex": "Scan_(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})(?P<date_only>)", "examples": [{"filename": "Scan_2019-07-04.jpg", "timestamp": "2019-07-04"}]}]' check-config
+  assert_output --partial "INFO: Configuration is OK"
+}
+
+@test "a date-only filename gives a prefix with the placeholder time" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/Scan_2019-07-04.gif"
+  run -0 ./target/debug/picnamion --set 'extra_file_regexes=["Scan_(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})(?P<date_only>)"]' --set date_only_time=12:00:00 "$BATS_TEST_TMPDIR/Scan_2019-07-04.gif"
+  assert_output --partial "INFO: Prefix determined: 2019-07-04_12-00-00--"
+}
+
+@test "two different dates and nothing else are offered as choices" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/Scan_2019-07-04_printed_2019-08-01.gif"
+  dates='extra_file_regexes=["Scan_(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})(?P<date_only>)", "printed_(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-(?P<day>[0-9]{2})(?P<date_only>)"]'
+  run -0 ./target/debug/picnamion --set "$dates" --set date_only_time=12:00:00 -m --review-dir "$BATS_TEST_TMPDIR/review" "$BATS_TEST_TMPDIR/Scan_2019-07-04_printed_2019-08-01.gif"
+  run -0 ./target/debug/picnamion resolve "$BATS_TEST_TMPDIR/review"
+  assert_output --partial "1. 2019-07-04T12:00:00 from date only, Scan_"
+  assert_output --partial "2. 2019-08-01T12:00:00 from date only, printed_"
+}
+
+@test "built-in filename patterns can be used by name" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/20190704_153337.gif"
+  run -0 ./target/debug/picnamion --set 'extra_file_regexes=["builtin:samsung"]' "$BATS_TEST_TMPDIR/20190704_153337.gif"
+  assert_output --partial "INFO: Prefix determined: 2019-07-04_15-33-37--"
+  run -1 ./target/debug/picnamion --set 'extra_file_regexes=["builtin:nope"]' check-config
+  assert_output --partial "unknown built-in file regex \"builtin:nope\""
+}
+
+@test "a filename with an offset matches metadata that's the same moment" {
+  # 02:33:37 in home_time_zone (America/Los_Angeles, -07:00 in July) is 15:33:37 at +06:00
+  python3 -c "import sys; d = open(sys.argv[1], 'rb').read(); open(sys.argv[2], 'wb').write(d.replace(b'2012:02:13 19:34:35', b'2019:07:04 02:33:37'))" "test_data/2012-02-13 19.34.36.jpg" "$BATS_TEST_TMPDIR/Cam_20190704_153337+0600.jpg"
+  run -0 ./target/debug/picnamion --set home_time_zone=America/Los_Angeles --set 'extra_file_regexes=["Cam_(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})(?P<offset>[+-][0-9]{4})"]' explain "$BATS_TEST_TMPDIR/Cam_20190704_153337+0600.jpg"
+  assert_output --partial "exact_match: Filename timestamp is exactly the same as a metadata candidate"
+  assert_output --partial "Result: decided, prefix 2019-07-04_15-33-37-- (ExactMatch)"
+  refute_output --partial "whole_hour_mismatch"
+}
+
+@test "a PXL filename a whole number of hours off the metadata is still an hour shift" {
+  # The PXL name is UTC, 17:23:52 at home; the metadata says 19:23:52
+  python3 -c "import sys; d = open(sys.argv[1], 'rb').read(); open(sys.argv[2], 'wb').write(d.replace(b'2025:07:08 17:23:52', b'2025:07:08 19:23:52'))" test_data/PXL_20250709_002352285.jpg "$BATS_TEST_TMPDIR/PXL_20250709_002352285.jpg"
+  run -0 ./target/debug/picnamion --set home_time_zone=America/Los_Angeles explain "$BATS_TEST_TMPDIR/PXL_20250709_002352285.jpg"
+  assert_output --partial "whole_hour_match: Filename timestamp is a whole 2 hours off a metadata candidate"
+  assert_output --partial "Result: decided, prefix 2025-07-08_17-23-52-- (HourShift)"
+}
+
+@test "a subsec group that matched digits from another script is a bad filename timestamp" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/IMG_20190704_153337_１２３４.gif"
+  run -2 ./target/debug/picnamion --set 'extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})_(?P<subsec>\\d+)"]' "$BATS_TEST_TMPDIR/IMG_20190704_153337_１２３４.gif"
+  assert_output --partial 'got a bad subsec "１２３４"'
+  refute_output --partial "panicked"
+}
+
+@test "two regexes finding the same filename timestamp don't make it ambiguous" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/IMG_20190704_153337.gif"
+  run -0 ./target/debug/picnamion --set 'extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})"]' explain "$BATS_TEST_TMPDIR/IMG_20190704_153337.gif"
+  assert_output --partial "2 filename regexes matched with the same timestamp"
+  assert_output --partial "Result: decided, prefix 2019-07-04_15-33-37-- (FilenameOnly)"
+}
+
+@test "check-config catches matching tolerances that don't fit together" {
+  run -1 ./target/debug/picnamion --set nearby_time_zone_hours=13 check-config
+  assert_output --partial "nearby_time_zone_hours (13) must be between 0 and utc_filename_hours (12)"
+  run -1 ./target/debug/picnamion --set merge_hours=-1 check-config
+  assert_output --partial "merge_hours must not be negative, not -1"
+}
+
+@test "explain shows the configured scoring points" {
+  run -0 ./target/debug/picnamion --set utc_copy_penalty=2 explain "test_data/2012-02-13 19.34.36.jpg"
+  assert_output --partial "no_time_zone_utc_copy: Also added a copy that assumes it was really UTC, at 2 points less"
+}
+
+@test "--min-confidence leaves less certain files alone and lists them" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/IMG_20190704_153337.gif"
+  run -0 ./target/debug/picnamion -m --min-confidence 60 "$BATS_TEST_TMPDIR/IMG_20190704_153337.gif"
+  assert_output --partial "WARNING: Left 1 file(s) alone because their confidence is below 60"
+  assert_output --partial "IMG_20190704_153337.gif: 2019-07-04_15-33-37-- (FilenameOnly, confidence 50)"
+  assert [ -e "$BATS_TEST_TMPDIR/IMG_20190704_153337.gif" ]
+}
+
+@test "a run ends with a summary and its exit status says what went wrong" {
+  mkdir "$BATS_TEST_TMPDIR/mixed"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/mixed/IMG_20190704_153337.gif"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/mixed/2019-07-04_15-33-37--old.gif"
+  echo hello > "$BATS_TEST_TMPDIR/mixed/notes.txt"
+  run -0 ./target/debug/picnamion "$BATS_TEST_TMPDIR/mixed"
+  assert_output --regexp "decided \(dry run, not moved\) +1"
+  assert_output --regexp "not media +1"
+  assert_output --regexp "skipped, already prefixed +1"
+  run -6 ./target/debug/picnamion --fail-on ambiguous,not-media "$BATS_TEST_TMPDIR/mixed"
+  cp "$BATS_TEST_TMPDIR/mixed/IMG_20190704_153337.gif" "$BATS_TEST_TMPDIR/mixed/2019-07-04_15-33-37--IMG_20190704_153337.gif"
+  run -3 ./target/debug/picnamion "$BATS_TEST_TMPDIR/mixed/IMG_20190704_153337.gif"
+  assert_output --regexp "collisions +1"
+}
+
+@test "one failing file doesn't stop the run unless --fail-fast" {
+  mkdir "$BATS_TEST_TMPDIR/bad" "$BATS_TEST_TMPDIR/good"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/bad/IMG_20190704_153337.gif"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/good/IMG_20190704_153337.gif"
+  echo '{ nearby_time_zone_hours: 20 }' > "$BATS_TEST_TMPDIR/bad/.picnamion.json5"
+  run -2 ./target/debug/picnamion "$BATS_TEST_TMPDIR/bad/IMG_20190704_153337.gif" "$BATS_TEST_TMPDIR/good/IMG_20190704_153337.gif"
+  assert_output --partial "ERROR: Failed to handle file $BATS_TEST_TMPDIR/bad/IMG_20190704_153337.gif"
+  assert_output --partial "INFO: Prefix determined: 2019-07-04_15-33-37--"
+  assert_output --regexp "errors +1"
+  run -1 ./target/debug/picnamion --fail-fast "$BATS_TEST_TMPDIR/bad/IMG_20190704_153337.gif" "$BATS_TEST_TMPDIR/good/IMG_20190704_153337.gif"
+  refute_output --partial "INFO: Prefix determined"
+}
+
+@test "errors say what couldn't be parsed and where" {
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/IMG_20191304_153337.gif"
+  run -2 ./target/debug/picnamion "$BATS_TEST_TMPDIR/IMG_20191304_153337.gif"
+  assert_output --partial "got a bad date and time \"2019-13-04T15:33:37.000000000\" from $BATS_TEST_TMPDIR/IMG_20191304_153337.gif"
+}
+
+@test "file names that aren't UTF-8 are renamed with their bytes intact" {
+  mkdir "$BATS_TEST_TMPDIR/latin1"
+  cp test_data/IMG_20130101_153337.jpg "$BATS_TEST_TMPDIR/latin1/$(printf 'caf\xe9_IMG_20130101_153337.jpg')"
+  run -0 ./target/debug/picnamion -m "$BATS_TEST_TMPDIR/latin1"
+  assert_output --partial "INFO: Prefix determined: 2013-01-01_15-33-37--"
+  assert [ -e "$BATS_TEST_TMPDIR/latin1/$(printf '2013-01-01_15-33-37--caf\xe9_IMG_20130101_153337.jpg')" ]
+}
+
+@test "--files-from reads a NUL-separated list of files and directories from stdin" {
+  mkdir "$BATS_TEST_TMPDIR/list" "$BATS_TEST_TMPDIR/list/dir"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/list/IMG_20190704_153337.gif"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/list/dir/IMG_20180101_101010.gif"
+  run -0 bash -c "printf '%s\0' '$BATS_TEST_TMPDIR/list/IMG_20190704_153337.gif' '$BATS_TEST_TMPDIR/list/dir' | ./target/debug/picnamion --files-from - -0"
+  assert_output --partial "INFO: Prefix determined: 2019-07-04_15-33-37--"
+  assert_output --partial "INFO: Prefix determined: 2018-01-01_10-10-10--"
+}
+
+@test "watch renames files once they settle and moves uncertain ones aside" {
+  mkdir "$BATS_TEST_TMPDIR/inbox"
+  ./target/debug/picnamion --set watch_settle_seconds=1 watch --log "$BATS_TEST_TMPDIR/watch.log" --min-confidence 40 "$BATS_TEST_TMPDIR/inbox" 3>&- &
+  watcher=$!
+  sleep 1
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/inbox/.syncthing.IMG_20190704_153337.gif.tmp"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/inbox/holiday.gif"
+  sleep 2
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/.syncthing.IMG_20190704_153337.gif.tmp" ]
+  mv "$BATS_TEST_TMPDIR/inbox/.syncthing.IMG_20190704_153337.gif.tmp" "$BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif"
+  sleep 3
+  kill "$watcher"
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/2019-07-04_15-33-37--IMG_20190704_153337.gif" ]
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/needs-review/holiday.gif" ]
+  run cat "$BATS_TEST_TMPDIR/watch.log"
+  assert_output --partial "because its confidence (FileTime, 10) is below 40"
+}
+
+@test "watch says a video was renamed even when rotating it fails" {
+  if [ -x /home/rlpowell/bin/video_hard_rotate.sh ]
   then
-    refute_output --partial "INFO: Prefix determined:"
-  else
-    assert_output --partial "INFO: Prefix determined: $(cat "$file.prefix" || echo "$file.prefix not found")"
+    skip "video_hard_rotate.sh is installed, so rotating won't fail"
   fi
+  mkdir "$BATS_TEST_TMPDIR/inbox"
+  ./target/debug/picnamion --set watch_settle_seconds=1 watch --log "$BATS_TEST_TMPDIR/watch.log" "$BATS_TEST_TMPDIR/inbox" 3>&- &
+  watcher=$!
+  sleep 1
+  cp test_data/screen-20251013-141005.mp4 "$BATS_TEST_TMPDIR/inbox/"
+  sleep 3
+  kill "$watcher"
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/2025-10-13_14-10-05--screen-20251013-141005.mp4" ]
+  run cat "$BATS_TEST_TMPDIR/watch.log"
+  assert_output --partial "ERROR: Renamed $BATS_TEST_TMPDIR/inbox/screen-20251013-141005.mp4 to $BATS_TEST_TMPDIR/inbox/2025-10-13_14-10-05--screen-20251013-141005.mp4, but failed to rotate it"
+  refute_output --partial "Failed to move file"
+}
+
+@test "--review-dir moves undecidable files aside with a sidecar that resolve reads" {
+  mkdir "$BATS_TEST_TMPDIR/inbox"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif"
+  swapped='extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<day>[0-9]{2})(?P<month>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})"]'
+  run -0 ./target/debug/picnamion --set "$swapped" -m --review-dir "$BATS_TEST_TMPDIR/review" "$BATS_TEST_TMPDIR/inbox"
+  assert_output --regexp "ambiguous, moved for review +1"
+  assert [ -e "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json" ]
+  run -0 ./target/debug/picnamion resolve "$BATS_TEST_TMPDIR/review"
+  assert_output --partial "2. 2019-04-07T15:33:37 from filename"
+  run -0 ./target/debug/picnamion resolve -m --pick 2 "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif"
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/2019-04-07_15-33-37--IMG_20190704_153337.gif" ]
+  assert [ ! -e "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json" ]
+}
+
+@test "--review-dir won't move a file aside over a sidecar that's already there" {
+  mkdir "$BATS_TEST_TMPDIR/inbox" "$BATS_TEST_TMPDIR/review"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif"
+  echo stale > "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json"
+  swapped='extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<day>[0-9]{2})(?P<month>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})"]'
+  run -3 ./target/debug/picnamion --set "$swapped" -m --review-dir "$BATS_TEST_TMPDIR/review" "$BATS_TEST_TMPDIR/inbox"
+  assert_output --partial "because $BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json already exists"
+  assert [ -e "$BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif" ]
+  run cat "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json"
+  assert_output "stale"
+}
+
+@test "--review-dir leaves no sidecar behind when it can't move the file" {
+  mkdir "$BATS_TEST_TMPDIR/inbox"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif"
+  if ! chattr +i "$BATS_TEST_TMPDIR/inbox" 2>/dev/null
+  then
+    skip "can't make the inbox immutable here"
+  fi
+  swapped='extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<day>[0-9]{2})(?P<month>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})"]'
+  run -2 ./target/debug/picnamion --set "$swapped" -m --review-dir "$BATS_TEST_TMPDIR/review" "$BATS_TEST_TMPDIR/inbox"
+  chattr -i "$BATS_TEST_TMPDIR/inbox"
+  assert_output --partial "ERROR: Failed to move file $BATS_TEST_TMPDIR/inbox/IMG_20190704_153337.gif"
+  assert [ ! -e "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif.picnamion.json" ]
+}
+
+@test "resolve puts a file back into a directory whose name isn't UTF-8" {
+  inbox="$BATS_TEST_TMPDIR/$(printf 'inbox\xe9')"
+  mkdir "$inbox"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$inbox/IMG_20190704_153337.gif"
+  swapped='extra_file_regexes=["IMG_(?P<year>[0-9]{4})(?P<day>[0-9]{2})(?P<month>[0-9]{2})_(?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})"]'
+  run -0 ./target/debug/picnamion --set "$swapped" -m --review-dir "$BATS_TEST_TMPDIR/review" "$inbox"
+  run -0 ./target/debug/picnamion resolve -m --pick 2 "$BATS_TEST_TMPDIR/review/IMG_20190704_153337.gif"
+  assert [ -e "$inbox/2019-04-07_15-33-37--IMG_20190704_153337.gif" ]
+  assert [ ! -e "$BATS_TEST_TMPDIR/$(printf 'inbox\xef\xbf\xbd')" ]
+}
+
+@test "an overrides file beats everything else and shows up in explain" {
+  mkdir -p "$BATS_TEST_TMPDIR/scans/1998"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/scans/1998/IMG_20190704_153337.gif"
+  printf 'match,timestamp,time_zone\nglob:1998/*,1998-06-12T14:30:00,\n' > "$BATS_TEST_TMPDIR/scans/.picnamion-overrides.csv"
+  run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/scans/1998/IMG_20190704_153337.gif"
+  assert_output --partial "override: Override glob:$BATS_TEST_TMPDIR/scans/1998/* ($BATS_TEST_TMPDIR/scans/.picnamion-overrides.csv line 2) says 1998-06-12T14:30:00"
+  assert_output --partial "Result: decided, prefix 1998-06-12_14-30-00-- (Override), confidence 100"
+  echo '[{ "match": "glob:scans/1998/*", "timestamp": "nope" }]' > "$BATS_TEST_TMPDIR/overrides.json"
+  run -1 ./target/debug/picnamion --set "overrides_file=$BATS_TEST_TMPDIR/overrides.json" check-config
+  assert_output --partial "bad timestamp \"nope\""
+}
+
+@test "overrides are a timestamp source that dates non-media files and can be disabled" {
+  mkdir -p "$BATS_TEST_TMPDIR/scans/1998"
+  printf 'GIF89a\x01\x00\x01\x00\x00\x00\x00;' > "$BATS_TEST_TMPDIR/scans/1998/IMG_20190704_153337.gif"
+  echo 'the back of the envelope' > "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  printf 'match,timestamp,time_zone\nglob:1998/*,1998-06-12,\n' > "$BATS_TEST_TMPDIR/scans/.picnamion-overrides.csv"
+  run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  assert_output --partial "source: Source overrides found 1 candidate(s)"
+  assert_output --partial "Result: decided, prefix 1998-06-12_00-00-00-- (Override), confidence 100"
+  run -0 ./target/debug/picnamion --set 'disabled_sources=["overrides"]' explain "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  assert_output --partial "Result: not_media"
+  run -0 ./target/debug/picnamion --set 'disabled_sources=["overrides"]' explain "$BATS_TEST_TMPDIR/scans/1998/IMG_20190704_153337.gif"
+  refute_output --partial "override:"
+  assert_output --partial "Result: decided, prefix 2019-07-04_15-33-37-- (FilenameOnly), confidence 50"
+}
+
+@test "a hash: override dates a non-media file with those contents, wherever it is" {
+  mkdir -p "$BATS_TEST_TMPDIR/scans/1998"
+  echo 'the back of the envelope' > "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  hash=$(sha256sum "$BATS_TEST_TMPDIR/scans/1998/notes.txt" | cut -d' ' -f1)
+  printf 'match,timestamp,time_zone
+glob:elsewhere/*,2001-01-01,
+hash:%s,1998-06-12,
+' "$hash" > "$BATS_TEST_TMPDIR/scans/.picnamion-overrides.csv"
+  run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  assert_output --partial "Override hash:$hash"
+  assert_output --partial "Result: decided, prefix 1998-06-12_00-00-00-- (Override), confidence 100"
+  echo 'the front of the envelope' > "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  run -0 ./target/debug/picnamion explain "$BATS_TEST_TMPDIR/scans/1998/notes.txt"
+  assert_output --partial "Result: not_media"
 }
@@ -0,0 +1,212 @@
+//! Golden tests: every file in test_data is decided on, and the whole decision (status, prefix,
+//! basis, confidence and every candidate timestamp the sources found) is compared against
+//! NAME.golden.json beside it.  NAME.prefix is the prefix it should get (NONE for none), and
+//! NAME.time is the modification time to give it first, since that's one of the sources.
+//!
+//! The decisions are made with just the built-in settings and the native metadata backend, so
+//! that no configuration file, PICNAMION_* variable or installed exiftool can change them.
+//!
+//! `UPDATE_GOLDEN=1 cargo test --test golden` rewrites the golden files from what picnamion
+//! decides now, so that a change to scoring shows up as a diff of them.
+
+use picnamion::configuration::Configuration;
+use picnamion::sources::CandidateTime;
+use picnamion::{Basis, Decision, Outcome, decide, metadata, prefix_for};
+use std::ffi::OsString;
+use std::fs::{self, FileTimes};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const FIXTURES: &str = "test_data";
+const GOLDEN: &str = ".golden.json";
+
+/// What goes in a golden file
+#[derive(serde::Serialize)]
+struct Golden {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    basis: Option<Basis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    choices: Vec<String>,
+    candidates: Vec<String>,
+}
+
+impl Golden {
+    fn new(decision: &Decision) -> Golden {
+        let (status, prefix, basis, choices) = match &decision.outcome {
+            Outcome::NotMedia => ("not_media", None, None, vec![]),
+            Outcome::Undecided { choices } => (
+                "undecided",
+                None,
+                None,
+                choices
+                    .iter()
+                    .map(|choice| format!("{} from {}", choice.ts, choice.from))
+                    .collect(),
+            ),
+            Outcome::Decided { ts, basis } => {
+                ("decided", Some(prefix_for(*ts)), Some(*basis), vec![])
+            }
+        };
+        let candidates = decision
+            .candidates
+            .iter()
+            .map(|candidate| match &candidate.time {
+                CandidateTime::Metadata(_) => format!(
+                    "{}: {}, score {}",
+                    candidate.provenance, candidate.time, candidate.score
+                ),
+                // The test can only set the modification time; reading the file changes the
+                // access time, and the inode change time is whenever the test ran
+                CandidateTime::FileTime(_) if candidate.provenance != "File FileModifyDate" => {
+                    format!(
+                        "{}: file time (whenever the test ran)",
+                        candidate.provenance
+                    )
+                }
+                _ => format!("{}: {}", candidate.provenance, candidate.time),
+            })
+            .collect();
+        Golden {
+            status,
+            prefix,
+            basis,
+            confidence: basis.map(|basis| basis.confidence()),
+            choices,
+            candidates,
+        }
+    }
+}
+
+/// `path` with `suffix` added to its name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+/// The files to decide on: everything in test_data that isn't one of the files about them
+fn fixtures() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = fs::read_dir(FIXTURES)
+        .expect("test_data is readable")
+        .map(|entry| entry.expect("test_data is readable").path())
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            ![".prefix", ".time", GOLDEN]
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// Sets a fixture's modification (and access) time from its .time file
+fn set_time(path: &Path) {
+    let seconds: u64 = fs::read_to_string(with_suffix(path, ".time"))
+        .unwrap_or_else(|e| panic!("{}.time: {e}", path.display()))
+        .trim()
+        .parse()
+        .unwrap_or_else(|e| panic!("{}.time: {e}", path.display()));
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_times(FileTimes::new().set_modified(time).set_accessed(time)))
+        .unwrap_or_else(|e| panic!("couldn't set the time of {}: {e}", path.display()));
+}
+
+/// The lines that differ between two versions of a golden file, diff style
+fn diff(expected: &str, got: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = got.lines().collect();
+
+    // Longest common subsequence lengths, from the ends
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            output += &format!("+{}\n", new[j]);
+            j += 1;
+        } else {
+            output += &format!("-{}\n", old[i]);
+            i += 1;
+        }
+    }
+    output
+}
+
+#[test]
+fn decisions_match_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some_and(|value| value == "1");
+    let configuration =
+        Configuration::builtin(&[("metadata_backend".to_string(), "native".to_string())]);
+    let settings = configuration
+        .settings_for(Path::new("."))
+        .expect("the configuration is good");
+    let mut backend = metadata::from_settings(&settings).expect("the metadata backend is good");
+
+    let mut failures: Vec<String> = vec![];
+    for file in fixtures() {
+        set_time(&file);
+        let decision = decide(&configuration, &file, backend.as_mut())
+            .unwrap_or_else(|e| panic!("couldn't decide on {}: {e:?}", file.display()));
+        let golden = Golden::new(&decision);
+
+        let expected_prefix = fs::read_to_string(with_suffix(&file, ".prefix"))
+            .unwrap_or_else(|e| panic!("{}.prefix: {e}", file.display()));
+        let prefix = golden.prefix.as_deref().unwrap_or("NONE");
+        if prefix != expected_prefix.trim() {
+            failures.push(format!(
+                "{}: got prefix {prefix}, but {}.prefix says {}",
+                file.display(),
+                file.display(),
+                expected_prefix.trim()
+            ));
+        }
+
+        let got = serde_json::to_string_pretty(&golden).unwrap() + "\n";
+        let golden_file = with_suffix(&file, GOLDEN);
+        if update {
+            fs::write(&golden_file, &got)
+                .unwrap_or_else(|e| panic!("couldn't write {}: {e}", golden_file.display()));
+            continue;
+        }
+        match fs::read_to_string(&golden_file) {
+            Ok(expected) if expected == got => {}
+            Ok(expected) => failures.push(format!(
+                "{} doesn't match the decision:\n{}",
+                golden_file.display(),
+                diff(&expected, &got)
+            )),
+            Err(_) => failures.push(format!(
+                "{} is missing; UPDATE_GOLDEN=1 creates it",
+                golden_file.display()
+            )),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nIf the new decisions are right, UPDATE_GOLDEN=1 cargo test --test golden updates the golden files.",
+        failures.join("\n\n")
+    );
+}